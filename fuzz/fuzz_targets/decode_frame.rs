@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use phantom_whisperer::helpers::decoder;
+use phantom_whisperer::DecodeOptions;
+
+// Deframed bytes straight into the FCS check and the parser, as if the HDLC receiver
+// had handed them over. Bad FCS frames are kept so every input reaches the parser.
+fuzz_target!(|frame: &[u8]| {
+    let options = DecodeOptions { keep_bad_fcs: true, ..DecodeOptions::default() };
+    let _ = decoder::decode_frame(frame.to_vec(), &options);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use phantom_whisperer::{decode_wav, DecodeOptions};
+
+// Whatever a client can upload to /decode, through the WAV reader and the whole receiver
+fuzz_target!(|upload: &[u8]| {
+    let _ = decode_wav(upload.to_vec(), &DecodeOptions::default());
+});
@@ -0,0 +1,340 @@
+use clap::{Args, Parser, Subcommand};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use phantom_whisperer::helpers::constants::{DEFAULT_TXDELAY_MS, DEFAULT_TXTAIL_MS, SAMPLE_RATE};
+use phantom_whisperer::helpers::segmentation::Reassembler;
+use phantom_whisperer::helpers::stream_decoder::StreamDecoder;
+use phantom_whisperer::helpers::{decoder, encoder, hdlc};
+use phantom_whisperer::models::aprs_packet::AprsPacket;
+use phantom_whisperer::models::decode_options::{DecodeOptions, DemodulatorKind, FixBits};
+use phantom_whisperer::models::decoded_frame::DecodedFrame;
+use phantom_whisperer::models::encode_options::{ChannelLayout, EncodeOptions, SampleFormat};
+use phantom_whisperer::models::reassembled_message::{ReassembledMessage, SegmentState};
+use phantom_whisperer::models::scheduled_frame::ScheduledFrame;
+
+// Command line interface, so the same codec can be used from shell scripts and CI
+// without running the web server:
+// - serve: start the web server
+// - encode: write a packet as a WAV file, or as raw PCM for pipelines
+// - decode: print the frames found in a WAV file, or in raw PCM read from stdin
+// - inspect: dump the bytes and the bit stream of a packet
+// Raw audio is signed 16 bit little endian mono PCM, like sox, rtl_fm and aplay use.
+// Decoded frames are printed to stdout in TNC2 monitor format, diagnostics go to stderr.
+
+#[derive(Parser)]
+#[command(name = "phantom_whisperer", about = "AX.25 / APRS AFSK1200 modem")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Start the web server (the default when no subcommand is given)
+    Serve(ServeArgs),
+    /// Encode a packet into a WAV file, or raw PCM
+    Encode(EncodeArgs),
+    /// Print every frame found in a WAV file, or in raw PCM from stdin
+    Decode(DecodeArgs),
+    /// Dump the frame bytes and bit stream of a packet
+    Inspect(InspectArgs),
+}
+
+#[derive(Args)]
+pub struct ServeArgs {
+    /// Address to listen on, use 0.0.0.0 inside docker
+    #[arg(short, long, default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST))]
+    pub bind: IpAddr,
+    /// Port to listen on
+    #[arg(short, long, default_value_t = 3030)]
+    pub port: u16,
+    /// Directory holding index.html and the static files
+    #[arg(long, default_value = "web")]
+    pub web_dir: PathBuf,
+}
+
+impl Default for ServeArgs {
+    fn default() -> Self {
+        ServeArgs {
+            bind: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 3030,
+            web_dir: PathBuf::from("web"),
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct PacketArgs {
+    /// Source callsign, with an optional SSID
+    #[arg(short, long)]
+    pub source: String,
+    /// Destination callsign, with an optional SSID
+    #[arg(short, long, default_value = "APRS")]
+    pub destination: String,
+    /// Digipeater path, comma separated (e.g. WIDE1-1,WIDE2-1)
+    #[arg(short, long, value_delimiter = ',')]
+    pub path: Vec<String>,
+    /// Text of the information field
+    #[arg(short, long)]
+    pub text: String,
+}
+
+#[derive(Args)]
+pub struct KeyingArgs {
+    /// Flags sent before each frame while the transmitter keys up, in milliseconds
+    #[arg(long, default_value_t = DEFAULT_TXDELAY_MS)]
+    pub txdelay: u32,
+    /// Flags sent after each frame before the transmitter unkeys, in milliseconds
+    #[arg(long, default_value_t = DEFAULT_TXTAIL_MS)]
+    pub txtail: u32,
+    /// Silence before the first frame, for VOX to trigger on, in milliseconds
+    #[arg(long, default_value_t = 0)]
+    pub silence: u32,
+}
+
+#[derive(Args)]
+pub struct EncodeArgs {
+    #[command(flatten)]
+    pub packet: PacketArgs,
+    #[command(flatten)]
+    pub keying: KeyingArgs,
+    /// File to write, stdout when missing
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Write raw mono 16 bit PCM instead of a WAV file
+    #[arg(long)]
+    pub raw: bool,
+    /// Sample rate of the generated audio, in Hz
+    #[arg(short, long, default_value_t = SAMPLE_RATE)]
+    pub rate: u32,
+    /// How samples are stored in the WAV file
+    #[arg(long, value_enum, default_value_t = SampleFormat::I16)]
+    pub format: SampleFormat,
+    /// Number of channels in the WAV file
+    #[arg(long, default_value_t = 1)]
+    pub channels: u16,
+    /// Which channels carry the signal
+    #[arg(long, value_enum, default_value_t = ChannelLayout::Both)]
+    pub layout: ChannelLayout,
+    /// Peak level of the tones, from 0.0 to 1.0 of full scale
+    #[arg(long, default_value_t = 1.0)]
+    pub amplitude: f32,
+    /// Glide between tones along a raised cosine
+    #[arg(long)]
+    pub shaping: bool,
+    /// How many times to transmit the packet
+    #[arg(long, default_value_t = 1)]
+    pub repeat: u8,
+    /// Silence between two transmissions, in milliseconds
+    #[arg(long, default_value_t = 0)]
+    pub gap: u32,
+}
+
+#[derive(Args)]
+pub struct InspectArgs {
+    #[command(flatten)]
+    pub packet: PacketArgs,
+    #[command(flatten)]
+    pub keying: KeyingArgs,
+}
+
+#[derive(Args)]
+pub struct DecodeArgs {
+    /// WAV file to decode, raw PCM is read from stdin when missing
+    pub file: Option<PathBuf>,
+    /// Sample rate of the raw PCM read from stdin, in Hz
+    #[arg(short, long, default_value_t = SAMPLE_RATE)]
+    pub rate: u32,
+    /// How hard to try repairing frames whose FCS does not validate
+    #[arg(long, value_enum, default_value_t = FixBits::None)]
+    pub fix_bits: FixBits,
+    /// Demodulator turning the audio into tones
+    #[arg(long, value_enum, default_value_t = DemodulatorKind::Correlator)]
+    pub demodulator: DemodulatorKind,
+    /// Run the whole bank of demodulator variants
+    #[arg(long)]
+    pub parallel: bool,
+    /// Also print segmented messages once put back together
+    #[arg(long)]
+    pub reassemble: bool,
+    /// Channel of the WAV file to decode, counting from 0, all channels are mixed when missing
+    #[arg(long)]
+    pub channel: Option<u16>,
+}
+
+/// Encodes a packet and writes the audio to a file or stdout
+pub fn encode(args: EncodeArgs) -> io::Result<()> {
+    let options = EncodeOptions {
+        amplitude: args.amplitude,
+        shaping: args.shaping,
+        sample_rate: args.rate,
+        sample_format: args.format,
+        channels: args.channels,
+        layout: args.layout,
+        txdelay_ms: args.keying.txdelay,
+        txtail_ms: args.keying.txtail,
+        leading_silence_ms: args.keying.silence,
+        gap_ms: args.gap,
+    };
+
+    let packet = &args.packet;
+    let frame = ScheduledFrame::new(AprsPacket::new(&packet.source, &packet.destination, &packet.path, &packet.text), args.repeat);
+    let bytes = if args.raw {
+        encoder::encode_frames(&[frame], &options).map(|samples| samples.iter().flat_map(|s| s.to_le_bytes()).collect())
+    } else {
+        encoder::encode_wav(&[frame], &options)
+    }
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    match args.output {
+        Some(path) => std::fs::write(path, bytes),
+        None => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&bytes)?;
+            stdout.flush()
+        }
+    }
+}
+
+/// Decodes a WAV file, or raw PCM from stdin until it closes, printing frames as they are decoded
+pub fn decode(args: DecodeArgs) -> io::Result<()> {
+    let options = DecodeOptions {
+        fix_bits: args.fix_bits,
+        demodulator: args.demodulator,
+        parallel: args.parallel,
+        reassemble: args.reassemble,
+        channel: args.channel,
+        ..DecodeOptions::default()
+    };
+    let mut reassembler = Reassembler::new();
+
+    if let Some(path) = args.file {
+        let frames = decoder::decode_wav(std::fs::read(path)?, &options)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        for frame in frames {
+            print_frame(&frame, &mut reassembler, &options);
+        }
+        print_incomplete_messages(reassembler, &options);
+        return Ok(());
+    }
+
+    let mut stream_decoder = StreamDecoder::new(args.rate, &options, |frame| {
+        print_frame(&frame, &mut reassembler, &options);
+    });
+
+    let mut stdin = io::stdin().lock();
+    let mut buffer = vec![0u8; 8192];
+    let mut pcm = Vec::new();
+
+    loop {
+        let read = match stdin.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        // A read may end in the middle of a sample, keep the odd byte for the next one
+        pcm.extend_from_slice(&buffer[..read]);
+        let whole = pcm.len() - pcm.len() % 2;
+        let samples: Vec<i16> = pcm[..whole]
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        pcm.drain(..whole);
+
+        stream_decoder.push_samples(&samples);
+    }
+
+    stream_decoder.flush();
+    print_incomplete_messages(reassembler, &options);
+    Ok(())
+}
+
+/// Prints a frame as a TNC2 monitor line, then the message it completes if reassembling
+fn print_frame(frame: &DecodedFrame, reassembler: &mut Reassembler, options: &DecodeOptions) {
+    println!("{}", frame.packet.to_tnc2());
+
+    if options.reassemble {
+        if let Some(message) = reassembler.push(frame) {
+            print_message(&message);
+        }
+    }
+}
+
+/// Prints the messages still missing segments at the end of the audio
+fn print_incomplete_messages(reassembler: Reassembler, options: &DecodeOptions) {
+    if options.reassemble {
+        reassembler.finish().iter().for_each(print_message);
+    }
+}
+
+/// Prints a reassembled message with the segments it was put together from
+fn print_message(message: &ReassembledMessage) {
+    let received = message.segments.iter().filter(|s| s.status == SegmentState::Received).count();
+    let missing: Vec<String> = message.segments.iter()
+        .filter(|s| s.status == SegmentState::Missing)
+        .map(|s| s.index.to_string())
+        .collect();
+
+    if message.complete {
+        println!("{}>{} message {} ({} segments): {}", message.source, message.destination, message.message_id, received, message.text);
+    } else {
+        println!("{}>{} message {} ({} of {} segments, missing {}): {}", message.source, message.destination, message.message_id,
+            received, message.segments.len(), missing.join(","), message.text);
+    }
+}
+
+/// Prints the AX.25 frame of a packet in hex, then the bits as they go over the air
+pub fn inspect(args: InspectArgs) -> io::Result<()> {
+    let options = EncodeOptions {
+        txdelay_ms: args.keying.txdelay,
+        txtail_ms: args.keying.txtail,
+        leading_silence_ms: args.keying.silence,
+        ..EncodeOptions::default()
+    };
+    let args = args.packet;
+    let packet = AprsPacket::new(&args.source, &args.destination, &args.path, &args.text);
+    let frame = packet.encode().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    println!("Packet: {}", packet.to_tnc2());
+    println!();
+    println!("Frame ({} bytes, FCS {:02X}{:02X}):", frame.len(), frame[frame.len() - 1], frame[frame.len() - 2]);
+    for (i, line) in frame.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02X}", b)).collect();
+        let ascii: String = line.iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        println!("  {:04X}  {:<48} {}", i * 16, hex.join(" "), ascii);
+    }
+
+    // Same bit stream the modulator sends: preamble, flagged and stuffed frame, postamble
+    let bits = encoder::transmission_bits(&frame, &options);
+    let stuffed_bits = bits.len() - (frame.len() + 2 + options.preamble_flags() + options.postamble_flags()) * 8;
+
+    println!();
+    if options.leading_silence_ms > 0 {
+        println!("Leading silence: {} ms", options.leading_silence_ms);
+    }
+    println!("HDLC bits ({} bits, {} flags of TXDELAY, {} of TXTAIL, {} stuffed, LSB first):",
+        bits.len(), options.preamble_flags(), options.postamble_flags(), stuffed_bits);
+    print_bits(&bits, |bit| if bit { '1' } else { '0' });
+
+    println!();
+    println!("NRZI tones (M = mark {} Hz, S = space):", phantom_whisperer::helpers::constants::MARK_FREQ);
+    print_bits(&hdlc::nrzi_encode(&bits), |tone| if tone { 'M' } else { 'S' });
+
+    Ok(())
+}
+
+/// Prints a bit stream in lines of 64, grouped by 8
+fn print_bits(bits: &[bool], symbol: impl Fn(bool) -> char) {
+    for line in bits.chunks(64) {
+        let groups: Vec<String> = line.chunks(8)
+            .map(|group| group.iter().map(|&b| symbol(b)).collect())
+            .collect();
+        println!("  {}", groups.join(" "));
+    }
+}
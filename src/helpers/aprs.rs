@@ -0,0 +1,105 @@
+use crate::helpers::errors::CodecError;
+use crate::helpers::{messaging, mic_e, objects, position};
+use crate::models::aprs_data::{AprsData, Timestamp};
+
+// APRS puts a data type identifier in the first character of the information field, it
+// tells how the rest is laid out. Position reports come in four flavours:
+// - '!' and '=': position without a timestamp, '=' when the station can take messages
+// - '/' and '@': a 7 character timestamp first, '@' when the station can take messages
+// The position itself, uncompressed or compressed, is read and written by the position module.
+// Mic-E reports start with '`' or '\'', and need the destination address as well.
+// Messages, acks and rejects start with ':', the messaging module reads and writes those.
+// Objects start with ';' and items with ')', the objects module reads and writes those.
+
+/// Reads the APRS data of a packet, looking at the destination address for the formats that use it
+/// Returns what it carries, or None for data types we don't parse and fields that break the format
+pub fn parse_packet(destination: &str, information: &str) -> Option<AprsData> {
+    match information.chars().next()? {
+        '`' | '\'' | '\u{1c}' | '\u{1d}' => mic_e::parse_mic_e(destination, information).map(AprsData::MicE),
+        _ => parse_information(information),
+    }
+}
+
+/// Reads the information field of an APRS packet
+/// Returns what it carries, or None for data types we don't parse and fields that break the format
+pub fn parse_information(information: &str) -> Option<AprsData> {
+    let mut chars = information.chars();
+    let identifier = chars.next()?;
+    let body = chars.as_str();
+
+    match identifier {
+        '!' | '=' => {
+            let mut report = position::parse_position(body)?;
+            report.messaging = identifier == '=';
+            Some(AprsData::Position(report))
+        }
+        '/' | '@' => {
+            let timestamp = parse_timestamp(body.get(..7)?)?;
+            let mut report = position::parse_position(body.get(7..)?)?;
+            report.timestamp = Some(timestamp);
+            report.messaging = identifier == '@';
+            Some(AprsData::Position(report))
+        }
+        ':' => messaging::parse_message(information),
+        ';' => objects::parse_object(information).map(AprsData::Object),
+        ')' => objects::parse_item(information).map(AprsData::Item),
+        _ => None,
+    }
+}
+
+/// Builds the destination address and the information field of a packet carrying APRS data
+/// Returns both, the destination given is kept unless the format puts data in it, then only its SSID is
+pub fn encode_packet(data: &AprsData, destination: &str) -> Result<(String, String), CodecError> {
+    match data {
+        AprsData::MicE(report) => {
+            let (mut callsign, information) = mic_e::encode_mic_e(report)?;
+            if let Some((_, ssid)) = destination.split_once('-') {
+                callsign = format!("{}-{}", callsign, ssid);
+            }
+            Ok((callsign, information))
+        }
+        _ => Ok((destination.to_string(), encode_information(data)?)),
+    }
+}
+
+/// Builds the information field carrying APRS data, data type identifier included
+/// Returns the field, or why the data can't be sent
+pub fn encode_information(data: &AprsData) -> Result<String, CodecError> {
+    match data {
+        AprsData::MicE(report) => Ok(mic_e::encode_mic_e(report)?.1),
+        AprsData::Message(message) => messaging::encode_text_message(message),
+        AprsData::Ack(ack) => messaging::encode_acknowledgement(ack, false),
+        AprsData::Reject(reject) => messaging::encode_acknowledgement(reject, true),
+        AprsData::Object(object) => objects::encode_object(object, false),
+        AprsData::Item(item) => objects::encode_object(item, true),
+        AprsData::Position(report) => {
+            let identifier = match (report.timestamp.is_some(), report.messaging) {
+                (false, false) => '!',
+                (false, true) => '=',
+                (true, false) => '/',
+                (true, true) => '@',
+            };
+            let timestamp = report.timestamp.map(|t| t.to_string()).unwrap_or_default();
+            Ok(format!("{}{}{}", identifier, timestamp, position::encode_position(report)?))
+        }
+    }
+}
+
+/// Reads a 7 character timestamp, whose last character tells the format
+/// Returns None if the format is unknown or a field is out of range
+pub fn parse_timestamp(field: &str) -> Option<Timestamp> {
+    let (digits, format) = field.split_at_checked(6)?;
+    if !digits.bytes().all(|d| d.is_ascii_digit()) {
+        return None;
+    }
+    let pair = |i: usize| digits[i..i + 2].parse::<u8>().ok();
+    let (first, second, third) = (pair(0)?, pair(2)?, pair(4)?);
+
+    let timestamp = match format {
+        "z" => Timestamp::DayHourMinuteUtc { day: first, hour: second, minute: third },
+        "/" => Timestamp::DayHourMinuteLocal { day: first, hour: second, minute: third },
+        "h" => Timestamp::HourMinuteSecond { hour: first, minute: second, second: third },
+        _ => return None,
+    };
+    timestamp.is_valid().then_some(timestamp)
+}
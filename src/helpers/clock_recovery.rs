@@ -0,0 +1,88 @@
+use super::constants::BAUD_RATE;
+
+// Digital PLL recovering the bit clock from the demodulator output, the same way
+// Direwolf and multimon-ng do it. A 32 bit counter advances by one bit period per
+// 2^32 every sample, and a bit is sampled each time it overflows. Every change
+// of sign in the demodulator output marks a bit boundary, where the counter should
+// read zero: when it doesn't, the counter is pulled towards zero, which moves the
+// sampling point back to the middle of the bit. While the transitions keep landing
+// where they are expected the loop is considered locked and corrects more gently.
+
+/// Fraction of the counter kept at a transition when locked onto a signal
+const LOCKED_INERTIA: f32 = 0.74;
+/// Fraction of the counter kept at a transition while searching for a signal
+const SEARCHING_INERTIA: f32 = 0.50;
+/// Transitions within this fraction of a bit from the expected point count as on time
+const ON_TIME_WINDOW: f32 = 0.25;
+/// Number of recent on time transitions needed to consider the loop locked
+const LOCK_THRESHOLD: u32 = 6;
+
+pub struct ClockRecovery {
+    pll: i32,
+    step: i32,
+    sampling_offset: i32,
+    last_level: bool,
+    on_time_transitions: u32,
+}
+
+impl ClockRecovery {
+    pub fn new(sample_rate: u32, sampling_phase: f32) -> Self {
+        // One bit period spans the whole 2^32 range of the counter
+        let step = (BAUD_RATE as f64 / sample_rate as f64 * 4_294_967_296.0) as u32 as i32;
+
+        ClockRecovery {
+            pll: 0,
+            step,
+            // The sampling point can be moved away from the middle of the bit by a fraction of a bit
+            sampling_offset: (sampling_phase as f64 * 4_294_967_296.0) as i32,
+            last_level: false,
+            on_time_transitions: 0,
+        }
+    }
+
+    /// Feeds one demodulator output sample (positive for mark) into the loop
+    /// Returns the tone sampled at the middle of a bit, when one is due
+    pub fn process(&mut self, demodulated: f32) -> Option<bool> {
+        let level = demodulated > 0.0;
+
+        let previous_pll = self.pll.wrapping_sub(self.sampling_offset);
+        self.pll = self.pll.wrapping_add(self.step);
+        let shifted_pll = self.pll.wrapping_sub(self.sampling_offset);
+
+        // Overflowing from positive to negative is the middle of the bit, or the chosen
+        // sampling point when an offset is set
+        let bit = if previous_pll > 0 && shifted_pll < 0 {
+            Some(level)
+        } else {
+            None
+        };
+
+        if level != self.last_level {
+            self.nudge();
+        }
+        self.last_level = level;
+
+        bit
+    }
+
+    /// Pulls the counter towards zero at a transition
+    fn nudge(&mut self) {
+        // How far from the expected boundary this transition came, as a fraction of a bit
+        let offset = self.pll as f32 / i32::MAX as f32 / 2.0;
+
+        // Keep score of how many recent transitions came when expected
+        if offset.abs() < ON_TIME_WINDOW {
+            self.on_time_transitions = (self.on_time_transitions + 1).min(LOCK_THRESHOLD * 2);
+        } else {
+            self.on_time_transitions = self.on_time_transitions.saturating_sub(2);
+        }
+
+        let inertia = if self.is_locked() { LOCKED_INERTIA } else { SEARCHING_INERTIA };
+        self.pll = (self.pll as f32 * inertia) as i32;
+    }
+
+    /// Whether recent transitions have been arriving where the loop expects them
+    pub fn is_locked(&self) -> bool {
+        self.on_time_transitions >= LOCK_THRESHOLD
+    }
+}
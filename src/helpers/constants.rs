@@ -3,6 +3,18 @@ pub const SAMPLE_RATE: u32 = 44100;
 pub const MARK_FREQ: f32 = 1200.0;
 pub const SPACE_FREQ: f32 = 2200.0;
 pub const BAUD_RATE: f32 = 1200.0;
-pub const SAMPLES_PER_BIT: usize = (SAMPLE_RATE as f32 / BAUD_RATE) as usize;
-pub const BITS_PER_SAMPLE: u16 = 16;
 pub const FLAG: u8 = 0x7E;
+pub const DEFAULT_TXDELAY_MS: u32 = 300;
+pub const DEFAULT_TXTAIL_MS: u32 = 100;
+pub const MAX_TXDELAY_MS: u32 = 5000;
+pub const MAX_TXTAIL_MS: u32 = 5000;
+pub const MAX_LEADING_SILENCE_MS: u32 = 10000;
+pub const MAX_GAP_MS: u32 = 5000;
+pub const MAX_TRANSMISSIONS: usize = 64;
+pub const MIN_FRAME_LEN: usize = 18;
+pub const MAX_FRAME_LEN: usize = 330;
+pub const MIN_SAMPLE_RATE: u32 = 8000;
+pub const MAX_SAMPLE_RATE: u32 = 192000;
+pub const MAX_SEGMENTS: usize = 255;
+pub const MAX_CHANNELS: u16 = 8;
+pub const I24_MAX: i32 = (1 << 23) - 1;
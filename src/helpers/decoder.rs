@@ -1,202 +1,294 @@
 use super::constants::*;
 use crate::models::aprs_packet::AprsPacket;
+use crate::models::decode_options::{DecodeOptions, FixBits};
+use crate::models::decoded_frame::{CrcStatus, DecodedFrame};
 use crc::{Crc, CRC_16_IBM_SDLC};
-use rustfft::{FftPlanner, num_complex::Complex};
-use bytes::Buf;
-use futures::TryStreamExt;
-use hound::WavReader;
-use crate::helpers::errors::CustomError;
-use warp::reject::Rejection;
-
-pub fn decode_audio(samples: &[i16]) -> Result<AprsPacket, String> {
-    let bits = demodulate_afsk(samples);
-    let bytes = bits_to_bytes(&bits);
-    parse_aprs_packet(&bytes)
+use hound::{SampleFormat, WavReader};
+use crate::helpers::errors::CodecError;
+use crate::helpers::{aprs, resampler};
+use crate::helpers::stream_decoder::StreamDecoder;
+use log::debug;
+
+/// Decodes every frame in a recording
+/// Returns the frames in the order they were received, empty if flags were heard but no frame survived
+pub fn decode_audio(samples: &[i16], sample_rate: u32, options: &DecodeOptions) -> Result<Vec<DecodedFrame>, CodecError> {
+    let mut frames = Vec::new();
+
+    // A whole recording is just a stream that arrives in one block
+    let mut stream_decoder = StreamDecoder::new(sample_rate, options, |frame| frames.push(frame));
+    stream_decoder.push_samples(samples);
+    stream_decoder.flush();
+
+    // Flags show up in any AFSK1200 transmission, even a mangled one
+    if !stream_decoder.heard_flag() {
+        return Err(CodecError::NoFlag);
+    }
+
+    Ok(frames)
 }
 
-fn demodulate_afsk(samples: &[i16]) -> Vec<bool> {
-    let mut bits = Vec::new();
-    let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(SAMPLES_PER_BIT);
+/// Verifies (and if allowed, repairs) the FCS of a deframed AX.25 frame, then parses it
+/// Returns the decoded frame with its CRC status, its position in the audio is left for the caller to fill
+pub fn decode_frame(mut frame: Vec<u8>, options: &DecodeOptions) -> Result<DecodedFrame, CodecError> {
+    if frame.len() < MIN_FRAME_LEN {
+        return Err(CodecError::TruncatedFrame);
+    }
 
-    for chunk in samples.chunks(SAMPLES_PER_BIT) {
-        let mut buffer: Vec<Complex<f32>> = chunk.iter()
-            .map(|&s| Complex::new(s as f32, 0.0))
-            .collect();
+    let (crc, fixed_bits) = match verify_fcs(&mut frame, options.fix_bits) {
+        Ok(0) => (CrcStatus::Valid, 0),
+        Ok(fixed_bits) => (CrcStatus::Corrected, fixed_bits),
+        Err(_) if options.keep_bad_fcs => (CrcStatus::Invalid, 0),
+        Err(e) => return Err(e),
+    };
+    let packet = parse_aprs_packet(&frame)?;
+    let (_, fcs) = frame_crcs(&frame);
+
+    Ok(DecodedFrame {
+        packet,
+        sample_offset: 0,
+        timestamp: 0.0,
+        audio_level: 0,
+        crc,
+        fixed_bits,
+        fcs,
+        decoders: Vec::new(),
+    })
+}
 
-        fft.process(&mut buffer);
+/// Checks the frame check sequence, which covers everything from the destination address to the end of the information field
+/// Returns the number of bits that had to be flipped for the FCS to validate
+fn verify_fcs(frame: &mut [u8], fix_bits: FixBits) -> Result<u32, CodecError> {
+    let (calculated_crc, packet_crc) = frame_crcs(frame);
+    debug!("FCS check: calculated {:04X}, found {:04X}", calculated_crc, packet_crc);
 
-        let mark_bin = (MARK_FREQ * SAMPLES_PER_BIT as f32 / SAMPLE_RATE as f32).round() as usize;
-        let space_bin = (SPACE_FREQ * SAMPLES_PER_BIT as f32 / SAMPLE_RATE as f32).round() as usize;
+    if calculated_crc == packet_crc {
+        return Ok(0);
+    }
 
-        let mark_energy = buffer[mark_bin].norm_sqr();
-        let space_energy = buffer[space_bin].norm_sqr();
+    let total_bits = frame.len() * 8;
+
+    // Try flipping every single bit, like Direwolf's "fix bits" mode
+    if fix_bits >= FixBits::Single {
+        for bit in 0..total_bits {
+            flip_bit(frame, bit);
+            if fcs_matches(frame) {
+                debug!("FCS fixed by flipping bit {}", bit);
+                return Ok(1);
+            }
+            flip_bit(frame, bit);
+        }
+    }
 
-        bits.push(mark_energy > space_energy);
+    // Then every pair of adjacent bits, the usual shape of a burst error
+    if fix_bits >= FixBits::Double {
+        for bit in 0..total_bits - 1 {
+            flip_bit(frame, bit);
+            flip_bit(frame, bit + 1);
+            if fcs_matches(frame) {
+                debug!("FCS fixed by flipping bits {} and {}", bit, bit + 1);
+                return Ok(2);
+            }
+            flip_bit(frame, bit);
+            flip_bit(frame, bit + 1);
+        }
     }
 
-    bits
+    Err(CodecError::BadFcs { expected: calculated_crc, found: packet_crc })
 }
 
-fn bits_to_bytes(bits: &[bool]) -> Vec<u8> {
-    bits.chunks(8)
-        .map(|chunk| chunk.iter().fold(0, |acc, &b| (acc << 1) | (b as u8)))
-        .collect()
+/// Computes the CRC of the frame contents and reads the FCS sent with it
+/// Returns both values as (calculated, found), a frame too short to carry an FCS never matches
+fn frame_crcs(frame: &[u8]) -> (u16, u16) {
+    let Some((contents, fcs)) = frame.split_last_chunk::<2>() else {
+        return (0, 1);
+    };
+    let crc = Crc::<u16>::new(&CRC_16_IBM_SDLC);
+    (crc.checksum(contents), u16::from_le_bytes(*fcs))
 }
 
-fn parse_aprs_packet(bytes: &[u8]) -> Result<AprsPacket, String> {
-    println!("[DECODER] --> 6. Starting parse_aprs_packet");
-    if bytes.len() < 16 {
-        return Err("Packet too short".to_string());
-    }
+fn fcs_matches(frame: &[u8]) -> bool {
+    let (calculated_crc, packet_crc) = frame_crcs(frame);
+    calculated_crc == packet_crc
+}
 
-    // Find start flag in byte stream
-    let mut packet_byte_position = 0;
+fn flip_bit(frame: &mut [u8], bit: usize) {
+    frame[bit / 8] ^= 1 << (bit % 8);
+}
 
-    // Iterate through the bytes until the flag is found
-    while packet_byte_position < bytes.len() && bytes[packet_byte_position] != FLAG {
-        packet_byte_position += 1;
-    }
+/// Reads a frame front to back without ever indexing past its end
+/// Every read hands back None instead of panicking when the frame runs out
+struct FrameCursor<'a> {
+    bytes: &'a [u8],
+}
 
-    // If no flag is found, return an error
-    if packet_byte_position == bytes.len() {
-        return Err("No start flag found".to_string());
-    } else {
-        println!("[DECODER] --> 7. Found start flag at index: {}", packet_byte_position);
-        packet_byte_position += 1;
+impl<'a> FrameCursor<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        FrameCursor { bytes }
     }
 
-    // Decode addres Destination
-    let destination = decode_address(&bytes[packet_byte_position..packet_byte_position + 7]);
-
-    // Advance packet byte position
-    packet_byte_position += 7;
-
-    // Decode address Source
-    let source = decode_address(&bytes[packet_byte_position..packet_byte_position + 7]);
-    println!("[DECODER] --> 8. Decoded addresses: Destination: {}, Source: {}", destination, source);
-
-    // Advance packet byte position
-    packet_byte_position += 7;
-
-    // Decode Digipeater addresses
-    let mut digipeaters = Vec::new();
-    while packet_byte_position + 7 <= bytes.len() {
-        let address_bytes = &bytes[packet_byte_position..packet_byte_position + 7];
-        let digipeater = decode_address(address_bytes);
-        digipeaters.push(digipeater);
-        packet_byte_position += 7;
-
-        if (address_bytes[6] & 0x01) != 0 {
-            break; // Last bit is 1, indicating this is the last address
+    /// Takes the next `count` bytes
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        if count > self.bytes.len() {
+            return None;
         }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Some(taken)
     }
-    println!("[DECODER] --> 9. Decoded {} digipeater(s): {:?}", digipeaters.len(), digipeaters);
 
-    // Iterate through the bytes until the LSB is found
-    while packet_byte_position < bytes.len() && (bytes[packet_byte_position - 1] & 0x01) == 0 {
-        packet_byte_position += 7;
+    /// Takes the next 7 byte address field
+    fn take_address(&mut self) -> Option<&'a [u8; 7]> {
+        let (address, rest) = self.bytes.split_first_chunk::<7>()?;
+        self.bytes = rest;
+        Some(address)
     }
-    println!("[DECODER] --> 10. Found packet_byte_position at index: {}", packet_byte_position);
 
-    if packet_byte_position + 2 >= bytes.len() {
-        println!("[DECODER] --> 11. No information field");
-        return Err("No information field".to_string());
+    /// Takes everything left except the trailing `count` bytes
+    fn take_all_but(&mut self, count: usize) -> Option<&'a [u8]> {
+        let length = self.bytes.len().checked_sub(count)?;
+        self.take(length)
     }
+}
 
-    // Skip control and protocol ID fields
-    packet_byte_position += 2;
+/// Parses a deframed AX.25 frame (addresses through FCS, no flags) whose FCS was already verified
+/// Returns the APRS packet it carries, or what is wrong with the frame however it was mangled
+fn parse_aprs_packet(bytes: &[u8]) -> Result<AprsPacket, CodecError> {
+    debug!("Starting parse_aprs_packet");
+    let mut cursor = FrameCursor::new(bytes);
 
-    // Extract information field (excluding CRC and ending flag)
-    let info_end = bytes.len() - 3;
-    let information = String::from_utf8_lossy(&bytes[packet_byte_position..info_end]).to_string();
-    println!("[DECODER] --> 11. Extracted information field: {}", information);
+    // Decode addresses Destination and Source
+    let destination = decode_address(cursor.take_address().ok_or(CodecError::TruncatedAddress)?)?;
+    let source_bytes = cursor.take_address().ok_or(CodecError::TruncatedAddress)?;
+    let source = decode_address(source_bytes)?;
+    debug!("Decoded addresses: Destination: {}, Source: {}", destination, source);
 
-    // Verify CRC
-    let crc = Crc::<u16>::new(&CRC_16_IBM_SDLC);
-    let crc_range = &bytes[packet_byte_position..info_end];
-    let calculated_crc = crc.checksum(crc_range);
-    let packet_crc = u16::from_le_bytes([bytes[info_end], bytes[info_end + 1]]);
-    println!("[DECODER] --> 12. CRC check: calculated {:04X}, found {:04X}", calculated_crc, packet_crc);
+    // Decode Digipeater addresses, present only if the source is not the last address
+    let mut digipeaters = Vec::new();
+    let mut last_address = (source_bytes[6] & 0x01) != 0;
+    while !last_address {
+        let address_bytes = cursor.take_address().ok_or(CodecError::TruncatedAddress)?;
+        digipeaters.push(decode_address(address_bytes)?);
 
-    if calculated_crc != packet_crc {
-        println!("[DECODER] --> 13. CRC check failed");
-        return Err(format!("CRC mismatch: calculated {:04X}, found {:04X}", calculated_crc, packet_crc));
+        // Last bit is 1, indicating this is the last address
+        last_address = (address_bytes[6] & 0x01) != 0;
     }
+    debug!("Decoded {} digipeater(s): {:?}", digipeaters.len(), digipeaters);
 
-    println!("[DECODER] --> 13. CRC check passed");
+    // Skip control and protocol ID fields, then the information field runs up to the FCS
+    cursor.take(2).ok_or(CodecError::TruncatedFrame)?;
+    let information_bytes = cursor.take_all_but(2).ok_or(CodecError::TruncatedFrame)?;
+    let information = String::from_utf8_lossy(information_bytes).to_string();
+    debug!("Extracted information field: {}", information);
 
-    // Check for ending flag
-    if bytes[info_end + 2] != FLAG {
-        println!("[DECODER] --> 15. No ending flag found");
-        return Err("No ending flag found".to_string());
-    } else {
-        println!("[DECODER] --> 15. Found ending flag");
-    }
+    // Whatever APRS data the information field carries comes along with the raw text
+    let aprs = aprs::parse_packet(&destination, &information);
 
     Ok(AprsPacket {
         destination,
         source,
         digipeaters,
-        information
+        information,
+        aprs,
     })
 }
 
-fn decode_address(bytes: &[u8]) -> String {
-    let callsign: String = bytes[0..6]
+fn decode_address(bytes: &[u8; 7]) -> Result<String, CodecError> {
+    let (callsign_bytes, ssid_byte) = (&bytes[..6], bytes[6]);
+
+    // Callsign characters are shifted left by one, so their low bit must be clear
+    if callsign_bytes.iter().any(|&b| b & 0x01 != 0) {
+        let raw: String = callsign_bytes.iter().map(|&b| (b >> 1) as char).collect();
+        return Err(CodecError::InvalidCallsign(raw));
+    }
+
+    let callsign: String = callsign_bytes
         .iter()
         .map(|&b| (b >> 1) as char)
         .collect::<String>()
         .trim()
         .to_string();
 
-    let ssid = (bytes[6] >> 1) & 0x0F;
+    // Callsigns only use uppercase letters and digits, anything else is a corrupted frame
+    if callsign.is_empty() || !callsign.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+        return Err(CodecError::InvalidCallsign(callsign));
+    }
+
+    let ssid = (ssid_byte >> 1) & 0x0F;
 
     if ssid == 0 {
-        callsign
+        Ok(callsign)
     } else {
-        format!("{}-{}", callsign, ssid)
+        Ok(format!("{}-{}", callsign, ssid))
     }
 }
 
-pub async fn extract_wav_from_multipart(mut form: warp::multipart::FormData) -> Result<Vec<i16>, Rejection> {
-    // Extract the uploaded file from the form data
-    let part = form
-        .try_next()
-        .await
-        .map_err(|e| warp::reject::custom(CustomError(format!("Form data error: {}", e))))?
-        .ok_or_else(|| warp::reject::custom(CustomError("No file uploaded".to_string())))?;
-
-    println!("[DECODER] --> 2. File part extracted from form data");
-
-    let file_bytes: Vec<u8> = part
-        .stream()
-        .try_fold(Vec::new(), |mut acc, chunk| async move {
-            acc.extend_from_slice(chunk.chunk());
-            Ok(acc)
-        })
-        .await
-        .map_err(|e| warp::reject::custom(CustomError(format!("File read error: {}", e))))?;
+/// Decodes every frame in a WAV file, whatever its format, rate and channels
+/// Returns the frames, with their sample offsets counted at the rate of the file
+pub fn decode_wav(file_bytes: Vec<u8>, options: &DecodeOptions) -> Result<Vec<DecodedFrame>, CodecError> {
+    let (samples, sample_rate) = read_wav(file_bytes, options.channel)?;
+
+    // Every recording is decoded at the same rate, the demodulators are tuned for it
+    let samples = resampler::resample(&samples, sample_rate, SAMPLE_RATE);
+    debug!("Resampled from {} Hz to {} Hz", sample_rate, SAMPLE_RATE);
 
-    println!("[DECODER] --> 3. File bytes read, size: {} bytes", file_bytes.len());
+    let mut frames = decode_audio(&samples, SAMPLE_RATE, options)?;
+    for frame in &mut frames {
+        frame.sample_offset = (frame.sample_offset as u64 * sample_rate as u64 / SAMPLE_RATE as u64) as usize;
+    }
+
+    Ok(frames)
+}
 
+/// Reads the samples out of a WAV file, converting 8 to 32 bit integers and 32 bit floats alike
+/// With a channel given only that one is kept, otherwise all channels are mixed together
+/// Returns mono 16 bit samples along with the sample rate from the file header
+pub fn read_wav(file_bytes: Vec<u8>, channel: Option<u16>) -> Result<(Vec<i16>, u32), CodecError> {
     // Create a cursor from the file bytes
     let cursor = std::io::Cursor::new(file_bytes);
 
     // Create a WavReader from the cursor
     let mut reader = WavReader::new(cursor)
-        .map_err(|e| warp::reject::custom(CustomError(format!("WAV parsing error: {}", e))))?;
+        .map_err(|e| CodecError::UnsupportedWavFormat(e.to_string()))?;
 
-    println!("[DECODER] --> 4. WAV file parsed successfully");
+    let spec = reader.spec();
+    debug!("WAV file parsed successfully: {:?}", spec);
 
-    // Read the samples into a Vec<i16>
-    let samples: Vec<i16> = reader
-        .samples::<i16>()
-        .collect::<Result<Vec<i16>, _>>()
-        .map_err(|e| warp::reject::custom(CustomError(format!("Sample reading error: {}", e))))?;
+    // The header is whatever the upload says it is, don't build filters for nonsense rates
+    if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&spec.sample_rate) {
+        return Err(CodecError::UnsupportedWavFormat(format!("sample rate of {} Hz", spec.sample_rate)));
+    }
+    if spec.channels == 0 {
+        return Err(CodecError::UnsupportedWavFormat("no channels".to_string()));
+    }
+    if let Some(channel) = channel.filter(|&c| c >= spec.channels) {
+        return Err(CodecError::UnsupportedWavFormat(format!("no channel {} in a {} channel file", channel, spec.channels)));
+    }
 
-    println!("[DECODER] --> 5. Samples read, count: {}", samples.len());
+    // Every format is brought to 16 bits, integers keep their top bits and floats are scaled
+    let samples: Vec<i16> = match (spec.sample_format, spec.bits_per_sample) {
+        (SampleFormat::Int, bits @ 8..=16) => reader.samples::<i32>()
+            .map(|s| s.map(|s| (s << (16 - bits)) as i16))
+            .collect::<Result<_, _>>(),
+        (SampleFormat::Int, bits @ 17..=32) => reader.samples::<i32>()
+            .map(|s| s.map(|s| (s >> (bits - 16)) as i16))
+            .collect::<Result<_, _>>(),
+        (SampleFormat::Float, 32) => reader.samples::<f32>()
+            .map(|s| s.map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16))
+            .collect::<Result<_, _>>(),
+        (format, bits) => return Err(CodecError::UnsupportedWavFormat(format!("{} bit {:?} samples", bits, format))),
+    }
+    .map_err(|e| CodecError::UnsupportedWavFormat(e.to_string()))?;
+
+    // Samples are interleaved, one frame holds one sample of every channel
+    let channels = spec.channels as usize;
+    let samples: Vec<i16> = samples.chunks_exact(channels)
+        .map(|frame| match channel {
+            Some(channel) => frame[channel as usize],
+            None => (frame.iter().map(|&s| s as i32).sum::<i32>() / channels as i32) as i16,
+        })
+        .collect();
 
-    Ok(samples)
+    debug!("Samples read, count: {}, sample rate: {} Hz", samples.len(), spec.sample_rate);
 
+    Ok((samples, spec.sample_rate))
 }
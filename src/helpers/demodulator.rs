@@ -0,0 +1,202 @@
+use super::constants::*;
+use super::filter::FirFilter;
+use crate::models::decode_options::DemodulatorKind;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+// The demodulator turns audio into a soft decision for every sample: positive when
+// the last bit worth of audio looks like mark, negative when it looks like space.
+// Deciding where bits start and end is left to the clock recovery that follows it.
+// All demodulators share the same trait so they can be swapped and compared on the
+// same recording.
+
+pub trait Demodulator {
+    /// Feeds one audio sample into the demodulator
+    /// Returns the soft decision for that sample, positive for mark and negative for space
+    fn process(&mut self, sample: f32) -> f32;
+}
+
+/// Tuning of one demodulator in the bank the decoder can run side by side.
+/// Radios differ in de-emphasis and audio levels, so a setting that misses a packet
+/// on one recording may catch it on another.
+pub struct DemodulatorVariant {
+    pub name: &'static str,
+    /// Width of the band-pass prefilter, as a multiple of the default width
+    pub prefilter_width: f32,
+    /// Gain applied to the space tone relative to mark, above 1 for de-emphasized audio
+    pub space_gain: f32,
+    /// Shift of the PLL sampling point, as a fraction of a bit
+    pub sampling_phase: f32,
+}
+
+/// The bank of variants, the first one is the only one used unless the decoder is asked for all of them
+pub const VARIANTS: [DemodulatorVariant; 7] = [
+    DemodulatorVariant { name: "default", prefilter_width: 1.0, space_gain: 1.0, sampling_phase: 0.0 },
+    DemodulatorVariant { name: "space-boost", prefilter_width: 1.0, space_gain: 2.0, sampling_phase: 0.0 },
+    DemodulatorVariant { name: "space-cut", prefilter_width: 1.0, space_gain: 0.5, sampling_phase: 0.0 },
+    DemodulatorVariant { name: "wide", prefilter_width: 1.5, space_gain: 1.0, sampling_phase: 0.0 },
+    DemodulatorVariant { name: "narrow", prefilter_width: 0.6, space_gain: 1.0, sampling_phase: 0.0 },
+    DemodulatorVariant { name: "early", prefilter_width: 1.0, space_gain: 1.0, sampling_phase: -0.2 },
+    DemodulatorVariant { name: "late", prefilter_width: 1.0, space_gain: 1.0, sampling_phase: 0.2 },
+];
+
+/// Builds the demodulator selected in the decode options, tuned as the variant says
+/// Returns it boxed so the decoder doesn't need to know which one it is running
+pub fn new_demodulator(kind: DemodulatorKind, sample_rate: u32, variant: &DemodulatorVariant) -> Box<dyn Demodulator + Send> {
+    match kind {
+        DemodulatorKind::Fft => Box::new(FftDemodulator::new(sample_rate, variant)),
+        DemodulatorKind::Correlator => Box::new(CorrelatorDemodulator::new(sample_rate, variant)),
+    }
+}
+
+/// Band-pass filter keeping the Bell 202 band ahead of either demodulator, as wide as the variant says
+fn prefilter(sample_rate: u32, variant: &DemodulatorVariant) -> FirFilter {
+    let samples_per_bit = sample_rate as f32 / BAUD_RATE;
+
+    // By default keep the band from half a baud below mark to half a baud above space
+    let center = (MARK_FREQ + SPACE_FREQ) / 2.0;
+    let half_width = (SPACE_FREQ - MARK_FREQ + BAUD_RATE) / 2.0 * variant.prefilter_width;
+
+    FirFilter::band_pass(center - half_width, center + half_width, sample_rate, (samples_per_bit * 2.0) as usize)
+}
+
+/// Sliding FFT demodulator comparing the mark and space bins over one bit of band-pass filtered audio
+pub struct FftDemodulator {
+    prefilter: FirFilter,
+    window: VecDeque<f32>,
+    window_len: usize,
+    fft: Arc<dyn Fft<f32>>,
+    buffer: Vec<Complex<f32>>,
+    mark_bin: usize,
+    space_bin: usize,
+    space_gain: f32,
+}
+
+impl FftDemodulator {
+    pub fn new(sample_rate: u32, variant: &DemodulatorVariant) -> Self {
+        // The window is one bit long, rounded down
+        let window_len = (sample_rate as f32 / BAUD_RATE).floor() as usize;
+        let fft = FftPlanner::new().plan_fft_forward(window_len);
+
+        FftDemodulator {
+            prefilter: prefilter(sample_rate, variant),
+            window: VecDeque::with_capacity(window_len),
+            window_len,
+            fft,
+            buffer: vec![Complex::new(0.0, 0.0); window_len],
+            mark_bin: (MARK_FREQ * window_len as f32 / sample_rate as f32).round() as usize,
+            space_bin: (SPACE_FREQ * window_len as f32 / sample_rate as f32).round() as usize,
+            // Bins hold energy, so the gain applies squared
+            space_gain: variant.space_gain * variant.space_gain,
+        }
+    }
+
+}
+
+impl Demodulator for FftDemodulator {
+    /// Feeds one audio sample through the prefilter into the sliding window
+    /// Returns mark energy minus space energy over the last bit of audio
+    fn process(&mut self, sample: f32) -> f32 {
+        if self.window.len() == self.window_len {
+            self.window.pop_front();
+        }
+        self.window.push_back(self.prefilter.process(sample));
+
+        // Not enough audio yet to hold a whole bit
+        if self.window.len() < self.window_len {
+            return 0.0;
+        }
+
+        for (slot, &s) in self.buffer.iter_mut().zip(self.window.iter()) {
+            *slot = Complex::new(s, 0.0);
+        }
+        self.fft.process(&mut self.buffer);
+
+        self.buffer[self.mark_bin].norm_sqr() - self.buffer[self.space_bin].norm_sqr() * self.space_gain
+    }
+}
+
+/// Quadrature correlator demodulator, a matched filter for each tone.
+/// The audio is band-pass filtered to the Bell 202 band, then multiplied by a mark and a
+/// space local oscillator. Summing those products over one bit gives the amplitude of
+/// each tone independently of its phase, and the difference of the two amplitudes is
+/// low-pass filtered to smooth out the ripple left by the correlation.
+pub struct CorrelatorDemodulator {
+    prefilter: FirFilter,
+    mark: Correlator,
+    space: Correlator,
+    space_gain: f32,
+    lowpass: FirFilter,
+}
+
+impl CorrelatorDemodulator {
+    pub fn new(sample_rate: u32, variant: &DemodulatorVariant) -> Self {
+        let samples_per_bit = sample_rate as f32 / BAUD_RATE;
+        let window_len = samples_per_bit.round() as usize;
+
+        CorrelatorDemodulator {
+            prefilter: prefilter(sample_rate, variant),
+            mark: Correlator::new(MARK_FREQ, sample_rate, window_len),
+            space: Correlator::new(SPACE_FREQ, sample_rate, window_len),
+            space_gain: variant.space_gain,
+            lowpass: FirFilter::low_pass(BAUD_RATE, sample_rate, samples_per_bit as usize),
+        }
+    }
+}
+
+impl Demodulator for CorrelatorDemodulator {
+    /// Feeds one audio sample through the filters and both correlators
+    /// Returns the smoothed difference between the mark and space amplitudes
+    fn process(&mut self, sample: f32) -> f32 {
+        let filtered = self.prefilter.process(sample);
+        let mark_amplitude = self.mark.process(filtered);
+        let space_amplitude = self.space.process(filtered) * self.space_gain;
+
+        self.lowpass.process(mark_amplitude - space_amplitude)
+    }
+}
+
+/// Sliding correlation of the input against one tone over a fixed window
+struct Correlator {
+    phase: f32,
+    phase_step: f32,
+    products: Vec<Complex<f32>>,
+    position: usize,
+    sum_re: f64,
+    sum_im: f64,
+}
+
+impl Correlator {
+    fn new(freq: f32, sample_rate: u32, window_len: usize) -> Self {
+        Correlator {
+            phase: 0.0,
+            phase_step: 2.0 * PI * freq / sample_rate as f32,
+            products: vec![Complex::new(0.0, 0.0); window_len],
+            position: 0,
+            sum_re: 0.0,
+            sum_im: 0.0,
+        }
+    }
+
+    /// Mixes one sample with the local oscillator and updates the running sum
+    /// Returns the amplitude of the tone over the window
+    fn process(&mut self, sample: f32) -> f32 {
+        let (sin, cos) = self.phase.sin_cos();
+        self.phase += self.phase_step;
+        if self.phase >= 2.0 * PI {
+            self.phase -= 2.0 * PI;
+        }
+
+        // Replace the product leaving the window with the new one
+        let product = Complex::new(sample * cos, -sample * sin);
+        let oldest = std::mem::replace(&mut self.products[self.position], product);
+        self.position = (self.position + 1) % self.products.len();
+
+        self.sum_re += (product.re - oldest.re) as f64;
+        self.sum_im += (product.im - oldest.im) as f64;
+
+        (self.sum_re * self.sum_re + self.sum_im * self.sum_im).sqrt() as f32
+    }
+}
@@ -1,10 +1,15 @@
 use crc::{Crc, CRC_16_IBM_SDLC};
 use crate::helpers::constants::*;
-use std::f32::consts::PI;
+use crate::helpers::errors::CodecError;
+use crate::helpers::{aprs, hdlc};
+use crate::helpers::segmentation;
+use crate::helpers::modulator::AfskModulator;
 use crate::models::aprs_packet::AprsPacket;
+use crate::models::encode_options::{ChannelLayout, EncodeOptions, SampleFormat};
+use crate::models::scheduled_frame::ScheduledFrame;
 use std::io::Cursor;
 use hound::{WavWriter, WavSpec};
-use warp::reject::Rejection;
+use log::debug;
 
 // https://hugosprojects.wordpress.com/2014/03/15/implementing-aprs/
 // | Field Name            | Number of Bytes | Example    |
@@ -19,73 +24,118 @@ use warp::reject::Rejection;
 // | Frame Check Sequence  | 2               | —          |
 // | Flag                  | 1               | 0x7E       |
 // |-----------------------|-----------------|------------|
+//
+// The flags are not part of the frame bytes, they are added by the HDLC layer
+// together with bit stuffing and NRZI coding right before modulation.
 
 
 /// Encodes a message into AFSK modulated audio samples
-/// Returns a vector of i16 audio samples representing the encoded message
-pub fn encode_message(source: &str, destination: &str, digipeaters: &Vec<String>, information: &str) -> Vec<i16> {
-    println!("[ENCODER] --> 2. Encoding message from {} to {}", source, destination);
-    // Prepare the APRS packets
-    let packets = prepare_packets(source, destination, digipeaters, information);
-    let mut audio_samples = Vec::new();
-
-    // Modulate each packet into audio samples
-    for (i, packet) in packets.iter().enumerate() {
-        println!("[ENCODER] --> 8. Modulating packet {} of {}", i + 1, packets.len());
-        audio_samples.extend(afsk_modulate(packet));
+/// Returns a vector of i16 audio samples representing the encoded message, or the first address that isn't a valid callsign
+pub fn encode_message(source: &str, destination: &str, digipeaters: &[String], information: &str, options: &EncodeOptions) -> Result<Vec<i16>, CodecError> {
+    debug!("Encoding message from {} to {}", source, destination);
+    let packet = AprsPacket::new(source, destination, digipeaters, information);
+    encode_frames(&[ScheduledFrame::new(packet, 1)], options)
+}
+
+/// Encodes several frames, each with its own addresses and repeat count, into one recording
+/// Returns the audio samples, with options.gap_ms of silence between two transmissions
+pub fn encode_frames(frames: &[ScheduledFrame], options: &EncodeOptions) -> Result<Vec<i16>, CodecError> {
+    let samples = encode_frames_float(frames, options)?;
+    Ok(samples.iter().map(|&sample| (sample * i16::MAX as f32) as i16).collect())
+}
+
+/// Encodes several frames into one recording, like encode_frames, without rounding the samples
+/// Returns mono audio samples from -1.0 to 1.0
+pub fn encode_frames_float(frames: &[ScheduledFrame], options: &EncodeOptions) -> Result<Vec<f32>, CodecError> {
+    // The options size every buffer below, refuse bad ones before anything is modulated
+    options.validate()?;
+
+    // Every transmission adds its keying and a gap to the recording, so keep their number bounded
+    let count: usize = frames.iter().map(|frame| frame.repeat as usize).sum();
+    if count > MAX_TRANSMISSIONS {
+        return Err(CodecError::InvalidOptions(format!("{} transmissions, at most {} fit in a recording", count, MAX_TRANSMISSIONS)));
     }
 
-    audio_samples
+    // Build every frame first, so a bad callsign anywhere fails before any audio is made
+    let mut transmissions = Vec::with_capacity(count);
+    for frame in frames {
+        let packet = &frame.packet;
+        let (destination, information) = packet_fields(packet)?;
+        let packets = prepare_packets(&packet.source, &destination, &packet.digipeaters, &information)?;
+        for _ in 0..frame.repeat {
+            transmissions.push(packets.clone());
+        }
+    }
+
+    // Give VOX circuits something to trigger on before the first flag
+    let mut audio_samples = vec![0.0; options.leading_silence_samples()];
+
+    // A single modulator keeps the phase continuous from one packet to the next
+    let mut modulator = AfskModulator::new(options);
+
+    for (i, packets) in transmissions.iter().enumerate() {
+        if i > 0 {
+            audio_samples.extend(std::iter::repeat_n(0.0, options.gap_samples()));
+        }
+
+        // Segments of one message go out back to back, in the same transmission
+        for (j, packet) in packets.iter().enumerate() {
+            debug!("Modulating packet {} of {} in transmission {} of {}", j + 1, packets.len(), i + 1, transmissions.len());
+            audio_samples.extend(afsk_modulate(packet, options, &mut modulator));
+        }
+    }
+
+    Ok(audio_samples)
 }
 
 
-/// Prepares APRS packets from a message, splitting it into chunks if necessary
+/// Picks the destination and information field to send, built from the APRS data when the packet only gives that
+/// Returns both, or why the APRS data can't be encoded
+fn packet_fields(packet: &AprsPacket) -> Result<(String, String), CodecError> {
+    match &packet.aprs {
+        Some(data) if packet.information.is_empty() => aprs::encode_packet(data, &packet.destination),
+        _ => Ok((packet.destination.clone(), packet.information.clone())),
+    }
+}
+
+/// Prepares APRS packets from a message, splitting it into segments if it doesn't fit in one frame
 /// Returns a vector of encoded APRS packets (each as a vector of bytes)
-pub fn prepare_packets(source: &str, destination: &str, digipeaters: &Vec<String>, information: &str) -> Vec<Vec<u8>> {
-    println!("[ENCODER] --> 3. Preparing packets for message: {}", information);
-    let mut packets = Vec::new();
-    // Split the message into chunks of MAX_PAYLOAD_SIZE
-    let chunks = information.as_bytes().chunks(MAX_PAYLOAD_SIZE);
-    let total_chunks = chunks.len();
-
-    for (i, chunk) in chunks.enumerate() {
-        println!("[ENCODER] --> 4. Processing chunk {} of {}", i + 1, total_chunks);
-        if total_chunks > 1 {
-            // If multiple chunks, add sequence number
-            format!("{{{}:{}}}{}", i + 1, total_chunks, String::from_utf8_lossy(chunk))
-        } else {
-            String::from_utf8_lossy(chunk).into_owned()
-        };
-
-        // Create and encode an APRS packet for each chunk
-        let packet = AprsPacket::new(source, destination, digipeaters, information);
-        packets.push(packet.encode());
+pub fn prepare_packets(source: &str, destination: &str, digipeaters: &[String], information: &str) -> Result<Vec<Vec<u8>>, CodecError> {
+    debug!("Preparing packets for message: {}", information);
+    let segments = segmentation::split_message(information)?;
+    let mut packets = Vec::with_capacity(segments.len());
+
+    for (i, segment) in segments.iter().enumerate() {
+        debug!("Processing segment {} of {}", i + 1, segments.len());
+
+        // Create and encode an APRS packet for each segment
+        let packet = AprsPacket::new(source, destination, digipeaters, segment);
+        packets.push(packet.encode()?);
     }
 
-    packets
+    Ok(packets)
 }
 
 impl AprsPacket {
-    pub fn encode(&self) -> Vec<u8> {
-        println!("[ENCODER] --> 5. Encoding APRS packet");
+    /// Builds the AX.25 frame for the packet, FCS included but without flags
+    /// Returns the frame bytes, or the first address that isn't a valid callsign
+    pub fn encode(&self) -> Result<Vec<u8>, CodecError> {
+        debug!("Encoding APRS packet");
         let mut packet = Vec::new();
 
-        // Add starting flag which will allow the decoder to find the start of the packet
-        packet.push(FLAG);
-
         // Add addresses
-        packet.extend(encode_address(&self.destination, false));
-        packet.extend(encode_address(&self.source, true));
+        packet.extend(encode_address(&self.destination, false)?);
+        packet.extend(encode_address(&self.source, self.digipeaters.is_empty())?);
 
-        println!("[ENCODER] --> 6. Encoding Destination: {}", self.destination);
-        println!("[ENCODER] --> 7. Encoding Source: {}", self.source);
+        debug!("Encoding Destination: {}", self.destination);
+        debug!("Encoding Source: {}", self.source);
 
 
         // Add Digipeater addresses
         for (i, digipeater) in self.digipeaters.iter().enumerate() {
-            println!("[ENCODER] --> 8. Encoding Digipeater: {}", digipeater);
+            debug!("Encoding Digipeater: {}", digipeater);
             let is_last = i == self.digipeaters.len() - 1;
-            packet.extend(encode_address(digipeater, is_last));
+            packet.extend(encode_address(digipeater, is_last)?);
         }
 
         // Control field and Protocol ID
@@ -93,46 +143,41 @@ impl AprsPacket {
         packet.push(0xf0); // Protocol ID: no layer 3
 
         // Information field
-        let info_field = self.information.as_bytes();
-        packet.extend(info_field);
+        packet.extend(self.information.as_bytes());
 
-        // Calculate CRC only on the information field
+        // Calculate CRC over the whole frame, addresses through information field
         let crc = Crc::<u16>::new(&CRC_16_IBM_SDLC);
-        let calculated_crc = crc.checksum(info_field);
+        let calculated_crc = crc.checksum(&packet);
         packet.extend(&calculated_crc.to_le_bytes());
 
-        // Add ending flag to mark the end of the packet
-        packet.push(FLAG);
-
-        println!("[ENCODER] --> 6. CRC calculated: {:04X}, appended to packet", calculated_crc);
+        debug!("CRC calculated: {:04X}, appended to packet", calculated_crc);
 
-        packet
+        Ok(packet)
     }
 }
 
 /// Encodes an APRS address (callsign-SSID) into the AX.25 format
-/// Returns a vector of bytes representing the encoded address
-pub fn encode_address(address: &str, last: bool) -> Vec<u8> {
-    let mut encoded = Vec::new();
+/// Returns the 7 address bytes, or an error if the callsign or SSID can't be represented
+pub fn encode_address(address: &str, last: bool) -> Result<Vec<u8>, CodecError> {
+    let invalid = || CodecError::InvalidCallsign(address.to_string());
+
     // Split the address into callsign and SSID parts
-    let parts: Vec<&str> = address.split('-').collect();
-    let callsign = parts[0].to_uppercase();
-    // Parse SSID, default to 0 if not present or invalid
-    let ssid = parts.get(1).unwrap_or(&"0").parse::<u8>().unwrap_or(0);
-
-    // Encode each character of the callsign
-    for (i, byte) in callsign.as_bytes().iter().enumerate() {
-        if i < 6 {
-            // Shift each byte left by 1 bit (AX.25 requirement)
-            encoded.push(byte << 1);
-        }
-    }
+    let (callsign, ssid) = match address.split_once('-') {
+        Some((callsign, ssid)) => (callsign, ssid.parse::<u8>().map_err(|_| invalid())?),
+        None => (address, 0),
+    };
+    let callsign = callsign.to_uppercase();
 
-    // Pad the callsign to 6 bytes if necessary
-    while encoded.len() < 6 {
-        encoded.push(b' ' << 1);
+    // Up to six letters and digits, and an SSID that fits in four bits
+    if callsign.is_empty() || callsign.len() > 6 || ssid > 15
+        || !callsign.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+        return Err(invalid());
     }
 
+    // Shift each byte left by 1 bit (AX.25 requirement), padding the callsign to 6 bytes
+    let mut encoded: Vec<u8> = callsign.bytes().map(|byte| byte << 1).collect();
+    encoded.resize(6, b' ' << 1);
+
     // Construct the SSID byte
     let mut ssid_byte = 0b01100000 | (ssid << 1);
     if last {
@@ -141,71 +186,91 @@ pub fn encode_address(address: &str, last: bool) -> Vec<u8> {
     }
     encoded.push(ssid_byte);
 
-    encoded
+    Ok(encoded)
 }
 
-/// Modulates a byte array into AFSK audio samples
-/// Returns a vector of i16 audio samples representing the modulated data
-fn afsk_modulate(data: &[u8]) -> Vec<i16> {
-    println!("[ENCODER] --> 9. AFSK modulating {} bytes", data.len());
-    let samples_per_bit = (SAMPLE_RATE as f32 / BAUD_RATE) as usize;
-    let mut audio_samples = Vec::new();
-
-    println!("[ENCODER] --> 10. Generating tones for each bit");
-    for &byte in data {
-        for bit in (0..8).rev() {
-            // Choose frequency based on the bit value using the modulation of Bell 202 tones
-            let freq = if (byte & (1 << bit)) != 0 { MARK_FREQ } else { SPACE_FREQ };
-            audio_samples.extend(generate_tone(freq, samples_per_bit));
-        }
-    }
+/// Builds the bits sent for one frame: TXDELAY flags, the flagged and stuffed frame, TXTAIL flags
+/// Returns the bits in the order they go over the air, before NRZI coding
+pub fn transmission_bits(frame: &[u8], options: &EncodeOptions) -> Vec<bool> {
+    // Start with a run of flags so the receiver's PLL can lock before the frame arrives
+    let mut bits = hdlc::flag_bits(options.preamble_flags());
 
-    audio_samples
+    // Add flags and bit stuffing
+    bits.extend(hdlc::frame_to_bits(frame));
+
+    // More flags after the frame give the receiver time to see the closing flag
+    bits.extend(hdlc::flag_bits(options.postamble_flags()));
+    bits
 }
 
-/// Generates a tone at a specified frequency
-/// Returns a vector of i16 audio samples representing the generated tone
-fn generate_tone(freq: f32, num_samples: usize) -> Vec<i16> {
-    (0..num_samples)
-        .map(|i| {
-            let t = i as f32 / SAMPLE_RATE as f32;
-            // Generate sine wave and scale to i16 range
-            (2.0 * PI * freq * t).sin() * i16::MAX as f32
-        })
-        .map(|sample| sample as i16)
-        .collect()
+/// Modulates an AX.25 frame into AFSK audio samples
+/// Returns audio samples from -1.0 to 1.0 representing the HDLC framed data
+fn afsk_modulate(frame: &[u8], options: &EncodeOptions, modulator: &mut AfskModulator) -> Vec<f32> {
+    debug!("AFSK modulating {} bytes", frame.len());
+
+    // NRZI code the bits into tones
+    let tones = hdlc::nrzi_encode(&transmission_bits(frame, options));
+
+    debug!("Generating tones for {} bits", tones.len());
+    modulator.modulate(&tones)
 }
 
 
-pub fn create_wav_file(audio_data: Vec<i16>) -> Result<impl warp::Reply, Rejection> {
-    println!("[ENCODER] --> 11. Starting create_wav_file function");
+/// Encodes several frames straight into a WAV file, in the rate, format and channels the options ask for
+/// Returns the bytes of the whole file
+pub fn encode_wav(frames: &[ScheduledFrame], options: &EncodeOptions) -> Result<Vec<u8>, CodecError> {
+    options.validate()?;
+    let samples = encode_frames_float(frames, options)?;
+    write_wav(&samples, options)
+}
+
+/// Wraps mono audio samples from -1.0 to 1.0 in a WAV file
+/// The signal is copied to the channels picked by the layout, the other channels stay silent
+/// Returns the bytes of the whole file, or an error if the options describe a file that can't be written
+pub fn write_wav(audio_data: &[f32], options: &EncodeOptions) -> Result<Vec<u8>, CodecError> {
+    options.validate()?;
 
+    let (bits_per_sample, sample_format) = match options.sample_format {
+        SampleFormat::I16 => (16, hound::SampleFormat::Int),
+        SampleFormat::I24 => (24, hound::SampleFormat::Int),
+        SampleFormat::F32 => (32, hound::SampleFormat::Float),
+    };
     let spec = WavSpec {
-        channels: 1,
-        sample_rate: SAMPLE_RATE,
-        bits_per_sample: BITS_PER_SAMPLE,
-        sample_format: hound::SampleFormat::Int,
+        channels: options.channels,
+        sample_rate: options.sample_rate,
+        bits_per_sample,
+        sample_format,
+    };
+    debug!("WAV spec created: {:?}", spec);
+
+    // Which channels of each frame carry the signal
+    let carries_signal = |channel: u16| match options.layout {
+        ChannelLayout::Left => channel == 0,
+        ChannelLayout::Right => channel == 1,
+        ChannelLayout::Both => true,
     };
-    println!("[ENCODER] --> 12. WAV spec created");
 
     let mut wav_buffer = Vec::new();
     {
         let mut writer = WavWriter::new(Cursor::new(&mut wav_buffer), spec).unwrap();
-        println!("[ENCODER] --> 13. WavWriter initialized");
-
-        for &sample in &audio_data {
-            writer.write_sample(sample).unwrap();
+        debug!("WavWriter initialized");
+
+        for &sample in audio_data {
+            for channel in 0..options.channels {
+                let sample = if carries_signal(channel) { sample } else { 0.0 };
+                match options.sample_format {
+                    SampleFormat::I16 => writer.write_sample((sample * i16::MAX as f32) as i16),
+                    SampleFormat::I24 => writer.write_sample((sample * I24_MAX as f32) as i32),
+                    SampleFormat::F32 => writer.write_sample(sample),
+                }
+                .unwrap();
+            }
         }
-        println!("[ENCODER] --> 14. Audio data written to WAV buffer");
+        debug!("Audio data written to WAV buffer");
 
         writer.finalize().unwrap();
-        println!("[ENCODER] --> 15. WAV file finalized");
+        debug!("WAV file finalized");
     }
 
-    println!("[ENCODER] --> 16. Returning WAV file data.\n");
-    Ok(warp::reply::with_header(
-        wav_buffer,
-        "Content-Type",
-        "audio/wav",
-    ))
+    Ok(wav_buffer)
 }
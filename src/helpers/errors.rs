@@ -1,28 +1,45 @@
 use std::{error::Error, fmt};
-impl warp::reject::Reject for CustomError {}
-use warp::reject::Rejection;
-use warp::Reply;
 
-// Custom error type for error handling
-#[derive(Debug)]
-pub struct CustomError(pub String);
+// Errors the codec can return, one variant per way a packet or a recording can be
+// unusable, so callers can react to each of them without parsing messages.
+// Nothing in here knows about HTTP, the web server maps these to responses on its side.
 
-impl Error for CustomError {}
-
-impl fmt::Display for CustomError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Custom error: {}", self.0)
-    }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    /// Not a single HDLC flag was heard, the audio doesn't carry AFSK1200 at all
+    NoFlag,
+    /// The address field runs past the end of the frame
+    TruncatedAddress,
+    /// The frame ends before its control, protocol ID or FCS fields
+    TruncatedFrame,
+    /// The FCS sent with the frame doesn't match the one calculated over its contents
+    BadFcs { expected: u16, found: u16 },
+    /// An address doesn't hold a valid callsign-SSID
+    InvalidCallsign(String),
+    /// The message needs more segments than a segment header can count
+    MessageTooLong { length: usize, max: usize },
+    /// The WAV file could not be parsed, or holds samples the decoder can't read
+    UnsupportedWavFormat(String),
+    /// APRS data to encode is out of range or doesn't fit the format it asks for
+    InvalidAprsData(String),
+    /// An encoder option asks for more audio than a recording is allowed to hold
+    InvalidOptions(String),
 }
 
-pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
-    if let Some(custom_error) = err.find::<CustomError>() {
-        let json = warp::reply::json(&serde_json::json!({
-            "error": custom_error.to_string()
-        }));
-        Ok(warp::reply::with_status(json, warp::http::StatusCode::BAD_REQUEST))
-    } else {
-        // Handle other rejections (optional)
-        Err(err)
+impl Error for CodecError {}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodecError::NoFlag => write!(f, "No HDLC flag found in the audio"),
+            CodecError::TruncatedAddress => write!(f, "Address field not terminated"),
+            CodecError::TruncatedFrame => write!(f, "Frame too short"),
+            CodecError::BadFcs { expected, found } => write!(f, "CRC mismatch: calculated {:04X}, found {:04X}", expected, found),
+            CodecError::InvalidCallsign(callsign) => write!(f, "Invalid callsign: {:?}", callsign),
+            CodecError::MessageTooLong { length, max } => write!(f, "Message of {} bytes is longer than the {} bytes that can be segmented", length, max),
+            CodecError::UnsupportedWavFormat(reason) => write!(f, "Unsupported WAV format: {}", reason),
+            CodecError::InvalidAprsData(reason) => write!(f, "Invalid APRS data: {}", reason),
+            CodecError::InvalidOptions(reason) => write!(f, "Invalid encoder options: {}", reason),
+        }
     }
 }
@@ -0,0 +1,77 @@
+use std::f32::consts::PI;
+
+// Windowed sinc FIR filters used around the demodulators. They only ever run one
+// sample at a time, so the filter keeps its own history in a ring buffer.
+
+pub struct FirFilter {
+    taps: Vec<f32>,
+    history: Vec<f32>,
+    position: usize,
+}
+
+impl FirFilter {
+    /// Builds a low-pass filter
+    /// Returns a filter passing everything below `cutoff` Hz
+    pub fn low_pass(cutoff: f32, sample_rate: u32, len: usize) -> Self {
+        let taps = windowed_sinc(cutoff / sample_rate as f32, len);
+        FirFilter::new(normalize(taps))
+    }
+
+    /// Builds a band-pass filter as the difference of two low-pass filters
+    /// Returns a filter passing everything between `low` and `high` Hz
+    pub fn band_pass(low: f32, high: f32, sample_rate: u32, len: usize) -> Self {
+        let upper = windowed_sinc(high / sample_rate as f32, len);
+        let lower = windowed_sinc(low / sample_rate as f32, len);
+        let taps = upper.iter().zip(lower.iter()).map(|(u, l)| u - l).collect();
+        FirFilter::new(taps)
+    }
+
+    fn new(taps: Vec<f32>) -> Self {
+        FirFilter {
+            history: vec![0.0; taps.len()],
+            taps,
+            position: 0,
+        }
+    }
+
+    /// Pushes one sample through the filter
+    /// Returns the filtered sample
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.history[self.position] = sample;
+        self.position = (self.position + 1) % self.history.len();
+
+        // Oldest sample first, the taps are symmetric so the direction doesn't matter
+        let (newer, older) = self.history.split_at(self.position);
+        older.iter()
+            .chain(newer.iter())
+            .zip(self.taps.iter())
+            .map(|(s, t)| s * t)
+            .sum()
+    }
+}
+
+/// Computes a Hamming windowed sinc for a cutoff given as a fraction of the sample rate
+/// Returns the filter taps, `len` is rounded up to an odd number so the filter has a center
+fn windowed_sinc(cutoff: f32, len: usize) -> Vec<f32> {
+    let len = len | 1;
+    let center = (len / 2) as f32;
+
+    (0..len)
+        .map(|i| {
+            let x = i as f32 - center;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * PI * cutoff * x).sin() / (PI * x)
+            };
+            let window = 0.54 - 0.46 * (2.0 * PI * i as f32 / (len - 1) as f32).cos();
+            sinc * window
+        })
+        .collect()
+}
+
+/// Scales the taps so the filter has unity gain at DC
+fn normalize(taps: Vec<f32>) -> Vec<f32> {
+    let sum: f32 = taps.iter().sum();
+    taps.into_iter().map(|t| t / sum).collect()
+}
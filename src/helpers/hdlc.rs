@@ -0,0 +1,201 @@
+use super::constants::{FLAG, MAX_FRAME_LEN, MIN_FRAME_LEN};
+
+// HDLC is the bit level layer AX.25 rides on. Before a frame reaches the modulator:
+// - Every byte is sent least significant bit first
+// - A 0 is stuffed after five consecutive 1s so the data can never look like a flag
+// - The frame is wrapped in 0x7E flags, which are never stuffed
+// - The resulting bits are NRZI coded: a 0 toggles the tone, a 1 keeps it
+
+
+/// Wraps an AX.25 frame in HDLC flags and applies bit stuffing
+/// Returns the bits to transmit, in the order they go over the air
+pub fn frame_to_bits(frame: &[u8]) -> Vec<bool> {
+    let mut bits = Vec::new();
+
+    // Opening flag, sent as is
+    push_flag(&mut bits);
+
+    let mut consecutive_ones = 0;
+    for &byte in frame {
+        for i in 0..8 {
+            let bit = (byte >> i) & 0x01 == 1;
+            bits.push(bit);
+
+            if bit {
+                consecutive_ones += 1;
+                // Stuff a 0 after five 1s in a row
+                if consecutive_ones == 5 {
+                    bits.push(false);
+                    consecutive_ones = 0;
+                }
+            } else {
+                consecutive_ones = 0;
+            }
+        }
+    }
+
+    // Closing flag, sent as is
+    push_flag(&mut bits);
+
+    bits
+}
+
+/// Builds a run of flags, sent ahead of a frame so the receiver can lock onto the signal
+/// Returns the bits of the flags, which are never stuffed
+pub fn flag_bits(count: usize) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(count * 8);
+    for _ in 0..count {
+        push_flag(&mut bits);
+    }
+    bits
+}
+
+/// Appends the flag byte to a bit stream without stuffing it
+fn push_flag(bits: &mut Vec<bool>) {
+    for i in 0..8 {
+        bits.push((FLAG >> i) & 0x01 == 1);
+    }
+}
+
+/// Applies NRZI line coding to a bit stream
+/// Returns the tone for each bit, true for mark and false for space
+pub fn nrzi_encode(bits: &[bool]) -> Vec<bool> {
+    // The line idles on mark
+    let mut tone = true;
+
+    bits.iter()
+        .map(|&bit| {
+            // A 0 is sent as a change of tone, a 1 as no change
+            if !bit {
+                tone = !tone;
+            }
+            tone
+        })
+        .collect()
+}
+
+/// What the HDLC receiver noticed on the bit it was just given
+#[derive(Debug, PartialEq)]
+pub enum HdlcEvent {
+    /// A flag that did not close a valid frame, a new frame may start after it
+    Flag,
+    /// A flag that closed a frame, with the frame bytes (FCS included)
+    Frame(Vec<u8>),
+}
+
+/// Bit level HDLC receiver
+/// Takes demodulated tones one at a time and hands back every complete frame it finds
+pub struct HdlcDecoder {
+    last_tone: bool,
+    consecutive_ones: u32,
+    in_frame: bool,
+    current_byte: u8,
+    bit_count: u8,
+    frame: Vec<u8>,
+}
+
+impl HdlcDecoder {
+    pub fn new() -> Self {
+        HdlcDecoder {
+            last_tone: true,
+            consecutive_ones: 0,
+            in_frame: false,
+            current_byte: 0,
+            bit_count: 0,
+            frame: Vec::new(),
+        }
+    }
+
+    /// Feeds one demodulated tone (true for mark) into the receiver
+    /// Returns an event whenever a flag arrives, carrying the frame if the flag closed one
+    pub fn push_tone(&mut self, tone: bool) -> Option<HdlcEvent> {
+        // NRZI decode: no change of tone is a 1, a change is a 0
+        let bit = tone == self.last_tone;
+        self.last_tone = tone;
+        self.push_bit(bit)
+    }
+
+    /// Feeds one NRZI decoded bit into the receiver
+    /// Returns an event whenever a flag arrives, carrying the frame if the flag closed one
+    pub fn push_bit(&mut self, bit: bool) -> Option<HdlcEvent> {
+        if bit {
+            self.consecutive_ones += 1;
+
+            // Seven or more 1s in a row is an abort, drop whatever we were collecting
+            if self.consecutive_ones >= 7 {
+                self.in_frame = false;
+                return None;
+            }
+        } else {
+            match self.consecutive_ones {
+                // 0111111 followed by this 0 is a flag, found at whatever bit offset it arrived
+                6 => {
+                    self.consecutive_ones = 0;
+                    let event = match self.finish_frame() {
+                        Some(frame) => HdlcEvent::Frame(frame),
+                        None => HdlcEvent::Flag,
+                    };
+                    self.start_frame();
+                    return Some(event);
+                }
+                // A 0 after five 1s was stuffed by the sender, drop it
+                5 => {
+                    self.consecutive_ones = 0;
+                    return None;
+                }
+                _ => self.consecutive_ones = 0,
+            }
+        }
+
+        if !self.in_frame {
+            return None;
+        }
+
+        // Bytes arrive least significant bit first
+        self.current_byte = (self.current_byte >> 1) | if bit { 0x80 } else { 0x00 };
+        self.bit_count += 1;
+
+        if self.bit_count == 8 {
+            self.frame.push(self.current_byte);
+            self.current_byte = 0;
+            self.bit_count = 0;
+
+            // Give up on anything longer than a valid frame can be
+            if self.frame.len() > MAX_FRAME_LEN {
+                self.in_frame = false;
+            }
+        }
+
+        None
+    }
+
+    /// Resets the byte collector after a flag
+    fn start_frame(&mut self) {
+        self.in_frame = true;
+        self.current_byte = 0;
+        self.bit_count = 0;
+        self.frame.clear();
+    }
+
+    /// Checks what was collected when a flag arrives
+    /// Returns the frame if it ended on a byte boundary and has a plausible length
+    fn finish_frame(&mut self) -> Option<Vec<u8>> {
+        // The first seven bits of the flag (0111111) have already been collected,
+        // so a frame that ended on a byte boundary leaves exactly seven bits behind
+        if !self.in_frame || self.bit_count != 7 {
+            return None;
+        }
+
+        if self.frame.len() < MIN_FRAME_LEN || self.frame.len() > MAX_FRAME_LEN {
+            return None;
+        }
+
+        Some(std::mem::take(&mut self.frame))
+    }
+}
+
+impl Default for HdlcDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
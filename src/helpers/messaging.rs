@@ -0,0 +1,277 @@
+use crate::helpers::constants::MAX_TRANSMISSIONS;
+use crate::helpers::errors::CodecError;
+use crate::models::aprs_data::{AprsData, Message, MessageAck};
+use crate::models::aprs_packet::AprsPacket;
+use crate::models::outgoing_message::{DeliveryState, MessageRequest, OutgoingMessage};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+// APRS messages are information fields of the form ":ADDRESSEE:text{ID":
+// - The addressee is padded with spaces to 9 characters, so the text always starts at the same place
+// - The text is up to 67 characters, without '|', '~' or '{', which have meanings of their own
+// - The ID after '{' is 1 to 5 letters and digits, and asks the addressee for an acknowledgement
+// The addressee answers with ":SENDER   :ackID" once it has the message, or "rejID" if it
+// won't take it. Newer stations add the ID of a message of their own to the answer,
+// "{ID}REPLYACK", only the ID before the '}' matters here.
+//
+// The message store keeps the messages we send until they are answered. Each one goes out
+// again after a wait that doubles every time, until its ack arrives or the retries run out.
+// Once answered or expired, a message stays listed until a hundred newer ones have finished too.
+
+const ADDRESSEE_LEN: usize = 9;
+const MAX_TEXT_LEN: usize = 67;
+const MAX_ID_LEN: usize = 5;
+/// The store numbers its messages from 1 up to this, then starts over
+const MAX_MESSAGE_NUMBER: u32 = 99999;
+/// Answered and expired messages the store keeps listing, the oldest ones are dropped past this
+const MAX_FINISHED_MESSAGES: usize = 100;
+
+/// Reads a message, ack or reject out of an information field starting with ':'
+/// Returns None if the addressee isn't padded to 9 characters and closed by a ':'
+pub fn parse_message(information: &str) -> Option<AprsData> {
+    let body = information.strip_prefix(':')?;
+    let addressee = body.get(..ADDRESSEE_LEN)?.trim_end().to_string();
+    let text = body.get(ADDRESSEE_LEN..)?.strip_prefix(':')?;
+    if addressee.is_empty() {
+        return None;
+    }
+
+    if let Some(id) = text.strip_prefix("ack").and_then(parse_id) {
+        return Some(AprsData::Ack(MessageAck { addressee, id }));
+    }
+    if let Some(id) = text.strip_prefix("rej").and_then(parse_id) {
+        return Some(AprsData::Reject(MessageAck { addressee, id }));
+    }
+
+    // Text with a '{' that isn't followed by an ID is just text
+    let (text, id) = match text.rsplit_once('{').map(|(text, id)| (text, parse_id(id))) {
+        Some((text, Some(id))) => (text, Some(id)),
+        _ => (text, None),
+    };
+
+    Some(AprsData::Message(Message { addressee, text: text.to_string(), id }))
+}
+
+/// Builds the information field of a message
+/// Returns the field, or why the message can't be sent
+pub fn encode_text_message(message: &Message) -> Result<String, CodecError> {
+    validate_addressee(&message.addressee)?;
+
+    let forbidden = |c: char| c.is_control() || matches!(c, '|' | '~' | '{');
+    if message.text.chars().count() > MAX_TEXT_LEN || message.text.contains(forbidden) {
+        return Err(CodecError::InvalidAprsData(format!("message text must be at most {} characters, without '|', '~' or '{{'", MAX_TEXT_LEN)));
+    }
+
+    let mut information = format!(":{:<width$}:{}", message.addressee, message.text, width = ADDRESSEE_LEN);
+    if let Some(id) = &message.id {
+        validate_id(id)?;
+        information.push('{');
+        information.push_str(id);
+    }
+
+    Ok(information)
+}
+
+/// Builds the information field of an ack, or of a reject
+/// Returns the field, or why it can't be sent
+pub fn encode_acknowledgement(ack: &MessageAck, reject: bool) -> Result<String, CodecError> {
+    validate_addressee(&ack.addressee)?;
+    validate_id(&ack.id)?;
+
+    let kind = if reject { "rej" } else { "ack" };
+    Ok(format!(":{:<width$}:{}{}", ack.addressee, kind, ack.id, width = ADDRESSEE_LEN))
+}
+
+/// Reads a message ID, ignoring a reply-ack after it
+fn parse_id(field: &str) -> Option<String> {
+    let id = field.split('}').next()?.trim_end();
+    validate_id(id).ok().map(|_| id.to_string())
+}
+
+fn validate_id(id: &str) -> Result<(), CodecError> {
+    if id.is_empty() || id.len() > MAX_ID_LEN || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(CodecError::InvalidAprsData(format!("message ID must be 1 to {} letters and digits", MAX_ID_LEN)));
+    }
+    Ok(())
+}
+
+fn validate_addressee(addressee: &str) -> Result<(), CodecError> {
+    if addressee.is_empty() || addressee.len() > ADDRESSEE_LEN || !addressee.chars().all(|c| c.is_ascii_graphic() && c != ':') {
+        return Err(CodecError::InvalidAprsData(format!("addressee must be 1 to {} printable characters", ADDRESSEE_LEN)));
+    }
+    Ok(())
+}
+
+/// How long the message store waits for an ack before sending a message again
+/// The wait starts at first_interval and is multiplied by factor after every attempt, up to
+/// max_interval. A message is given up on one wait after its last attempt.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub first_interval: Duration,
+    pub factor: u32,
+    pub max_interval: Duration,
+    pub max_attempts: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            first_interval: Duration::from_secs(30),
+            factor: 2,
+            max_interval: Duration::from_secs(8 * 60),
+            max_attempts: 5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait after the given attempt, counting from 1
+    fn interval(&self, attempt: u32) -> Duration {
+        let factor = self.factor.checked_pow(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.first_interval.saturating_mul(factor).min(self.max_interval)
+    }
+}
+
+/// Tracks the messages we send until their ack, their reject, or the end of their retries
+/// Time is passed in by the caller, the store never looks at the clock itself
+#[derive(Default)]
+pub struct MessageStore {
+    policy: RetryPolicy,
+    messages: Vec<OutgoingMessage>,
+    last_number: u32,
+}
+
+impl MessageStore {
+    pub fn new(policy: RetryPolicy) -> Self {
+        MessageStore { policy, messages: Vec::new(), last_number: 0 }
+    }
+
+    /// Gives a message an ID and queues it, its first transmission is due right away
+    /// Returns the queued message, or why it can't be sent
+    pub fn queue(&mut self, request: MessageRequest, now: Instant) -> Result<OutgoingMessage, CodecError> {
+        let message = OutgoingMessage {
+            source: request.source,
+            destination: request.destination,
+            digipeaters: request.digipeaters,
+            addressee: request.addressee,
+            text: request.text,
+            id: self.next_id(),
+            state: DeliveryState::Pending,
+            attempts: 0,
+            next_attempt: Some(now),
+        };
+
+        // Build the frame once, so a bad callsign or text fails now and not at the first retry
+        let information = encode_text_message(&message_for(&message))?;
+        AprsPacket::new(&message.source, &message.destination, &message.digipeaters, &information).encode()?;
+
+        self.messages.push(message.clone());
+        self.prune();
+        Ok(message)
+    }
+
+    /// Takes the messages due for a transmission, and gives up on those out of retries
+    /// Returns the packets to send now, in the order the messages were queued, as many as fit
+    /// in one recording. The others stay due for the next call
+    pub fn due(&mut self, now: Instant) -> Vec<AprsPacket> {
+        let mut packets = Vec::new();
+
+        for message in &mut self.messages {
+            if packets.len() == MAX_TRANSMISSIONS {
+                break;
+            }
+            if message.state != DeliveryState::Pending || message.next_attempt.is_none_or(|t| t > now) {
+                continue;
+            }
+
+            if message.attempts >= self.policy.max_attempts {
+                message.state = DeliveryState::Expired;
+                message.next_attempt = None;
+                continue;
+            }
+
+            message.attempts += 1;
+            message.next_attempt = Some(now + self.policy.interval(message.attempts));
+            packets.push(packet_for(message));
+        }
+
+        self.prune();
+        packets
+    }
+
+    /// Looks for the ack or reject of one of our messages in a received packet
+    /// Returns the message it answered, with its new state
+    pub fn handle(&mut self, packet: &AprsPacket) -> Option<OutgoingMessage> {
+        let (answer, state) = match &packet.aprs {
+            Some(AprsData::Ack(ack)) => (ack, DeliveryState::Delivered),
+            Some(AprsData::Reject(reject)) => (reject, DeliveryState::Rejected),
+            _ => return None,
+        };
+
+        // The answer comes from the addressee, addressed to whoever sent the message
+        let message = self.messages.iter_mut().find(|m| {
+            m.state == DeliveryState::Pending && m.id == answer.id
+                && m.addressee.eq_ignore_ascii_case(&packet.source) && m.source.eq_ignore_ascii_case(&answer.addressee)
+        })?;
+
+        message.state = state;
+        message.next_attempt = None;
+        let message = message.clone();
+        self.prune();
+        Some(message)
+    }
+
+    /// When the next transmission is due, if any message is still waiting for one
+    pub fn next_due(&self) -> Option<Instant> {
+        self.messages.iter().filter_map(|m| m.next_attempt).min()
+    }
+
+    /// Every message queued so far, answered or not
+    pub fn messages(&self) -> &[OutgoingMessage] {
+        &self.messages
+    }
+
+    /// Picks the next message number not used by a message still waiting for its ack
+    fn next_id(&mut self) -> String {
+        let pending: HashSet<&str> = self.messages.iter()
+            .filter(|m| m.state == DeliveryState::Pending)
+            .map(|m| m.id.as_str())
+            .collect();
+
+        for _ in 0..MAX_MESSAGE_NUMBER {
+            self.last_number = self.last_number % MAX_MESSAGE_NUMBER + 1;
+            let id = self.last_number.to_string();
+            if !pending.contains(id.as_str()) {
+                return id;
+            }
+        }
+
+        // Every number is waiting for an ack, the oldest one is the least likely to get it
+        self.last_number.to_string()
+    }
+
+    /// Drops the oldest answered and expired messages once there are too many of them
+    fn prune(&mut self) {
+        let finished = self.messages.iter().filter(|m| m.state != DeliveryState::Pending).count();
+        let mut excess = finished.saturating_sub(MAX_FINISHED_MESSAGES);
+        self.messages.retain(|m| {
+            let drop = excess > 0 && m.state != DeliveryState::Pending;
+            if drop {
+                excess -= 1;
+            }
+            !drop
+        });
+    }
+}
+
+/// The APRS message an outgoing message is sent as
+fn message_for(message: &OutgoingMessage) -> Message {
+    Message { addressee: message.addressee.clone(), text: message.text.clone(), id: Some(message.id.clone()) }
+}
+
+/// The packet an outgoing message is sent in, its information field is built from the APRS data
+fn packet_for(message: &OutgoingMessage) -> AprsPacket {
+    let mut packet = AprsPacket::new(&message.source, &message.destination, &message.digipeaters, "");
+    packet.aprs = Some(AprsData::Message(message_for(message)));
+    packet
+}
@@ -0,0 +1,322 @@
+use crate::helpers::errors::CodecError;
+use crate::helpers::position::{base91_decode, base91_encode, valid_symbol_table};
+use crate::models::aprs_data::{MicE, MicEMessage};
+
+// Mic-E squeezes a whole position report into as few bytes as it can by using the AX.25
+// destination address as well as the information field.
+//
+// The destination holds six characters, one latitude digit each (DDMMmm), and every one of
+// them carries a bit besides:
+// - '0'-'9' are the digit with a 0 bit, 'P'-'Y' the digit with a standard 1 bit and 'A'-'J'
+//   the digit with a custom 1 bit. 'L', 'Z' and 'K' are a digit hidden by ambiguity.
+// - The first three bits are the message code, custom bits are only allowed there
+// - The fourth is set for north, the fifth adds 100 to the longitude degrees, the sixth is set for west
+//
+// The information field starts with '`' (current GPS data) or '\'' (old), then 8 bytes each
+// offset by 28: longitude degrees, minutes and hundredths of a minute, then speed and course
+// spread over three bytes, the symbol code and the symbol table. Small numbers are moved up
+// a range (minutes by 60, speed by 800 knots, course by 400 degrees) so they stay printable.
+// The status text after that may start with a type code, then the altitude as 3 base-91
+// digits and '}' in meters above -10000, and can end with a suffix naming the radio.
+
+/// Every byte of the position, speed and course is sent with this added
+const OFFSET: u8 = 28;
+/// Altitude is sent in meters above this depth, so it's never negative
+const ALTITUDE_DEPTH: f64 = 10000.0;
+const FEET_PER_METER: f64 = 3.28084;
+
+/// Radios and trackers, by the type code after the symbol and the suffix ending the status text
+/// Radios with a suffix come first, so they aren't mistaken for one without
+const DEVICES: &[(char, &str, &str)] = &[
+    ('>', "=", "Kenwood TH-D72"),
+    ('>', "^", "Kenwood TH-D74"),
+    (']', "=", "Kenwood TM-D710"),
+    ('`', "_ ", "Yaesu VX-8"),
+    ('`', "_\"", "Yaesu FTM-350"),
+    ('`', "_#", "Yaesu VX-8G"),
+    ('`', "_$", "Yaesu FT1D"),
+    ('`', "_%", "Yaesu FTM-400DR"),
+    ('`', "_)", "Yaesu FTM-100D"),
+    ('`', "_(", "Yaesu FT2D"),
+    ('`', "_0", "Yaesu FT3D"),
+    ('`', "_1", "Yaesu FTM-300D"),
+    ('`', "_3", "Yaesu FT5D"),
+    ('\'', "|3", "Byonics TinyTrack3"),
+    ('\'', "|4", "Byonics TinyTrack4"),
+    ('>', "", "Kenwood TH-D7A"),
+    (']', "", "Kenwood TM-D700"),
+];
+
+/// The bit a destination character carries beside its latitude digit
+#[derive(Clone, Copy, PartialEq)]
+enum Bit {
+    Zero,
+    Standard,
+    Custom,
+}
+
+/// Reads a Mic-E report out of the destination callsign and the information field
+/// Returns None if either of them doesn't follow the format
+pub fn parse_mic_e(destination: &str, information: &str) -> Option<MicE> {
+    let callsign = destination.split('-').next()?;
+    let characters: Vec<(Option<u8>, Bit)> = callsign.bytes().map(destination_char).collect::<Option<_>>()?;
+    if characters.len() != 6 || characters[3..].iter().any(|&(_, bit)| bit == Bit::Custom) {
+        return None;
+    }
+
+    // Hidden digits are at the end, at most the four minute digits, and count as 0
+    let ambiguity = characters.iter().rev().take_while(|(digit, _)| digit.is_none()).count();
+    if ambiguity > 4 || characters[..6 - ambiguity].iter().any(|(digit, _)| digit.is_none()) {
+        return None;
+    }
+    let digits: Vec<u32> = characters.iter().map(|(digit, _)| digit.unwrap_or(0) as u32).collect();
+    let degrees = digits[0] * 10 + digits[1];
+    let hundredths = digits[2] * 1000 + digits[3] * 100 + digits[4] * 10 + digits[5];
+    let latitude = degrees as f64 + hundredths as f64 / 6000.0;
+    if hundredths >= 6000 || latitude > 90.0 {
+        return None;
+    }
+
+    let [north, longitude_offset, west] = [3, 4, 5].map(|i| characters[i].1 == Bit::Standard);
+    let message = message_code([characters[0].1, characters[1].1, characters[2].1]);
+
+    let current = match information.chars().next()? {
+        '`' | '\u{1c}' => true,
+        '\'' | '\u{1d}' => false,
+        _ => return None,
+    };
+    let field = information.get(1..9)?.as_bytes();
+    if !field.is_ascii() {
+        return None;
+    }
+    let values: Vec<u32> = field[..6].iter().map(|&b| b.checked_sub(OFFSET).map(u32::from)).collect::<Option<_>>()?;
+
+    let mut longitude_degrees = values[0] + if longitude_offset { 100 } else { 0 };
+    match longitude_degrees {
+        180..=189 => longitude_degrees -= 80,
+        190..=199 => longitude_degrees -= 190,
+        _ => {}
+    }
+    let longitude_minutes = values[1] % 60;
+    let (longitude_minutes, longitude_hundredths) = hide_longitude(longitude_minutes, values[2], ambiguity);
+    if longitude_degrees > 179 || values[1] >= 120 || longitude_hundredths > 99 {
+        return None;
+    }
+    let longitude = longitude_degrees as f64 + (longitude_minutes * 100 + longitude_hundredths) as f64 / 6000.0;
+
+    let speed = values[3] * 10 + values[4] / 10;
+    let speed = if speed >= 800 { speed - 800 } else { speed };
+    let course = (values[4] % 10) * 100 + values[5];
+    let course = if course >= 400 { course - 400 } else { course };
+    let (symbol_code, symbol_table) = (field[6] as char, field[7] as char);
+    if course > 360 || !valid_symbol_table(symbol_table) || !symbol_code.is_ascii_graphic() {
+        return None;
+    }
+
+    let mut status = information.get(9..)?;
+    let type_code = status.chars().next().filter(|c| matches!(c, '>' | ']' | '`' | '\''));
+    if type_code.is_some() {
+        status = &status[1..];
+    }
+
+    let mut altitude = None;
+    if status.get(3..4) == Some("}") {
+        if let Some(value) = base91_decode(&status.as_bytes()[..3]) {
+            altitude = Some(((value as f64 - ALTITUDE_DEPTH) * FEET_PER_METER).round());
+            status = &status[4..];
+        }
+    }
+
+    let mut device = None;
+    if let Some(&(_, suffix, name)) = DEVICES.iter().find(|&&(code, suffix, _)| Some(code) == type_code && status.ends_with(suffix)) {
+        device = Some(name.to_string());
+        status = &status[..status.len() - suffix.len()];
+    }
+
+    Some(MicE {
+        latitude: if north { latitude } else { -latitude },
+        longitude: if west { -longitude } else { longitude },
+        ambiguity: ambiguity as u8,
+        symbol_table,
+        symbol_code,
+        message,
+        speed: speed as u16,
+        course: course as u16,
+        altitude,
+        device,
+        current,
+        comment: status.to_string(),
+    })
+}
+
+/// Builds the destination callsign and the information field of a Mic-E report
+/// Returns both, or why the report can't be sent
+pub fn encode_mic_e(report: &MicE) -> Result<(String, String), CodecError> {
+    let invalid = |reason: &str| Err(CodecError::InvalidAprsData(reason.to_string()));
+
+    if !(-90.0..=90.0).contains(&report.latitude) || !(-180.0..=180.0).contains(&report.longitude) {
+        return invalid("latitude must be within 90 degrees and longitude within 180 degrees");
+    }
+    if !valid_symbol_table(report.symbol_table) || !report.symbol_code.is_ascii_graphic() {
+        return invalid("symbol table must be '/', '\\' or an overlay digit or capital letter, and the symbol code printable");
+    }
+    if report.ambiguity > 4 {
+        return invalid("ambiguity hides at most 4 digits");
+    }
+    if report.speed >= 800 || report.course > 360 {
+        return invalid("speed must be below 800 knots and course from 0 to 360 degrees");
+    }
+    if report.altitude.is_some_and(|a| !(-ALTITUDE_DEPTH * FEET_PER_METER..=(91f64.powi(3) - 1.0 - ALTITUDE_DEPTH) * FEET_PER_METER).contains(&a)) {
+        return invalid("altitude must be above -10000 meters and fit in 3 base-91 digits");
+    }
+    let Some(message_bits) = message_bits(report.message) else {
+        return invalid("an unknown message code can't be sent");
+    };
+    let (type_code, suffix) = match &report.device {
+        Some(name) => match DEVICES.iter().find(|&&(_, _, device)| device == name) {
+            Some(&(code, suffix, _)) => (code, suffix),
+            None => return invalid("unknown device, leave it out to send none"),
+        },
+        None => ('`', ""),
+    };
+
+    // Latitude digits, DDMMmm, the last `ambiguity` of them hidden
+    let latitude = (report.latitude.abs() * 6000.0).round() as u32;
+    let digits = format!("{:02}{:04}", latitude / 6000, latitude % 6000);
+
+    // Longitude, rounded to hundredths of a minute and kept short of 180 degrees
+    let longitude = ((report.longitude.abs() * 6000.0).round() as u32).min(180 * 6000 - 1);
+    let degrees = longitude / 6000;
+    let (minutes, hundredths) = hide_longitude(longitude % 6000 / 100, longitude % 100, report.ambiguity as usize);
+
+    let flags = [report.latitude >= 0.0, !(10..100).contains(&degrees), report.longitude < 0.0];
+    let destination: String = digits.bytes().enumerate()
+        .map(|(i, digit)| {
+            let digit = (i < 6 - report.ambiguity as usize).then_some(digit - b'0');
+            let bit = match i {
+                0..=2 => message_bits[i],
+                _ if flags[i - 3] => Bit::Standard,
+                _ => Bit::Zero,
+            };
+            destination_byte(digit, bit)
+        })
+        .collect();
+
+    let degrees_value = match degrees {
+        0..=9 => degrees + 90,
+        10..=99 => degrees,
+        100..=109 => degrees - 20,
+        _ => degrees - 100,
+    };
+    let minutes_value = if minutes < 10 { minutes + 60 } else { minutes };
+
+    let speed = report.speed as u32;
+    let speed_value = if speed < 200 { speed / 10 + 80 } else { speed / 10 };
+    let mut speed_course_value = speed % 10 * 10 + report.course as u32 / 100;
+    if speed_course_value < 4 {
+        speed_course_value += 4;
+    }
+    let course_value = report.course as u32 % 100;
+
+    let mut information = String::from(if report.current { '`' } else { '\'' });
+    for value in [degrees_value, minutes_value, hundredths, speed_value, speed_course_value, course_value] {
+        information.push((value as u8 + OFFSET) as char);
+    }
+    information.push(report.symbol_code);
+    information.push(report.symbol_table);
+    information.push(type_code);
+    if let Some(altitude) = report.altitude {
+        base91_encode((altitude / FEET_PER_METER + ALTITUDE_DEPTH).round() as u32, 3, &mut information);
+        information.push('}');
+    }
+    information.push_str(&report.comment);
+    information.push_str(suffix);
+
+    Ok((destination, information))
+}
+
+/// Drops the longitude digits the latitude ambiguity hides, the two always go together
+/// Returns the minutes and hundredths of a minute left
+fn hide_longitude(minutes: u32, hundredths: u32, ambiguity: usize) -> (u32, u32) {
+    let hundredths = match ambiguity {
+        0 => hundredths,
+        1 => hundredths / 10 * 10,
+        _ => 0,
+    };
+    let minutes = match ambiguity {
+        0..=2 => minutes,
+        3 => minutes / 10 * 10,
+        _ => 0,
+    };
+    (minutes, hundredths)
+}
+
+/// Reads one destination character
+/// Returns its latitude digit, None when hidden by ambiguity, and the bit it carries
+fn destination_char(character: u8) -> Option<(Option<u8>, Bit)> {
+    match character {
+        b'0'..=b'9' => Some((Some(character - b'0'), Bit::Zero)),
+        b'A'..=b'J' => Some((Some(character - b'A'), Bit::Custom)),
+        b'K' => Some((None, Bit::Custom)),
+        b'L' => Some((None, Bit::Zero)),
+        b'P'..=b'Y' => Some((Some(character - b'P'), Bit::Standard)),
+        b'Z' => Some((None, Bit::Standard)),
+        _ => None,
+    }
+}
+
+/// Writes one destination character from its latitude digit and bit
+fn destination_byte(digit: Option<u8>, bit: Bit) -> char {
+    let byte = match (digit, bit) {
+        (Some(digit), Bit::Zero) => b'0' + digit,
+        (Some(digit), Bit::Custom) => b'A' + digit,
+        (Some(digit), Bit::Standard) => b'P' + digit,
+        (None, Bit::Zero) => b'L',
+        (None, Bit::Custom) => b'K',
+        (None, Bit::Standard) => b'Z',
+    };
+    byte as char
+}
+
+/// Reads the message code out of the first three destination bits
+fn message_code(bits: [Bit; 3]) -> MicEMessage {
+    use MicEMessage::*;
+
+    let standard = bits.contains(&Bit::Standard);
+    let custom = bits.contains(&Bit::Custom);
+    let value = bits.iter().fold(0, |acc, &bit| acc << 1 | (bit != Bit::Zero) as usize);
+
+    match (standard, custom) {
+        (true, true) => Unknown,
+        (true, false) => [Emergency, Priority, Special, Committed, Returning, InService, EnRoute, OffDuty][value],
+        (false, true) => [Emergency, Custom6, Custom5, Custom4, Custom3, Custom2, Custom1, Custom0][value],
+        (false, false) => Emergency,
+    }
+}
+
+/// Splits a message code into the three destination bits carrying it
+/// Returns None for an unknown code, there's no way to send one
+fn message_bits(message: MicEMessage) -> Option<[Bit; 3]> {
+    use MicEMessage::*;
+
+    let (value, one) = match message {
+        OffDuty => (7, Bit::Standard),
+        EnRoute => (6, Bit::Standard),
+        InService => (5, Bit::Standard),
+        Returning => (4, Bit::Standard),
+        Committed => (3, Bit::Standard),
+        Special => (2, Bit::Standard),
+        Priority => (1, Bit::Standard),
+        Custom0 => (7, Bit::Custom),
+        Custom1 => (6, Bit::Custom),
+        Custom2 => (5, Bit::Custom),
+        Custom3 => (4, Bit::Custom),
+        Custom4 => (3, Bit::Custom),
+        Custom5 => (2, Bit::Custom),
+        Custom6 => (1, Bit::Custom),
+        Emergency => (0, Bit::Standard),
+        Unknown => return None,
+    };
+
+    Some([2, 1, 0].map(|shift| if value >> shift & 1 == 1 { one } else { Bit::Zero }))
+}
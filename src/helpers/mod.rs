@@ -1,4 +1,17 @@
+pub mod aprs;
 pub mod encoder;
 pub mod decoder;
+pub mod clock_recovery;
+pub mod demodulator;
+pub mod filter;
+pub mod mic_e;
+pub mod hdlc;
+pub mod messaging;
+pub mod modulator;
+pub mod objects;
+pub mod position;
+pub mod resampler;
+pub mod segmentation;
+pub mod stream_decoder;
 pub mod errors;
 pub mod constants;
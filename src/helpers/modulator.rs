@@ -0,0 +1,104 @@
+use super::constants::*;
+use crate::models::encode_options::EncodeOptions;
+use std::f32::consts::PI;
+
+// Bell 202 AFSK modulator built around a numerically controlled oscillator.
+// The oscillator phase is carried from one sample to the next, across bit boundaries
+// and across packets, so switching between mark and space never produces a jump
+// in the waveform. With shaping enabled, the frequency glides between tones along a
+// raised cosine centered on the bit boundary instead of stepping.
+// Bit timing is kept as an accumulated fraction of a bit, so sample rates that are not
+// a multiple of the baud rate (44100 / 1200 = 36.75) don't drift.
+
+/// Portion of a bit on each side of a boundary used to glide between tones
+const SHAPING_SPAN: f32 = 0.25;
+
+pub struct AfskModulator {
+    sample_rate: f32,
+    phase: f32,
+    bit_clock: f64,
+    bit_step: f64,
+    amplitude: f32,
+    shaping: bool,
+}
+
+impl AfskModulator {
+    pub fn new(options: &EncodeOptions) -> Self {
+        AfskModulator {
+            sample_rate: options.sample_rate as f32,
+            phase: 0.0,
+            bit_clock: 0.0,
+            bit_step: BAUD_RATE as f64 / options.sample_rate as f64,
+            amplitude: options.amplitude.clamp(0.0, 1.0),
+            shaping: options.shaping,
+        }
+    }
+
+    /// Modulates a sequence of tones (true for mark, false for space), one bit per tone
+    /// Returns audio samples from -1.0 to 1.0, continuing from the phase the previous call ended on
+    pub fn modulate(&mut self, tones: &[bool]) -> Vec<f32> {
+        let samples_per_bit = (1.0 / self.bit_step).ceil() as usize;
+        let mut audio_samples = Vec::with_capacity(tones.len() * samples_per_bit);
+
+        for (i, &mark) in tones.iter().enumerate() {
+            let freq = tone_freq(mark);
+            let previous_freq = if i > 0 { tone_freq(tones[i - 1]) } else { freq };
+            let next_freq = tones.get(i + 1).map(|&t| tone_freq(t)).unwrap_or(freq);
+
+            // Emit samples until the bit clock wraps, some bits get one sample more than others
+            while self.bit_clock < 1.0 {
+                // Position of this sample inside the bit, from 0 to 1
+                let position = self.bit_clock as f32;
+
+                let instant_freq = if self.shaping {
+                    shaped_freq(previous_freq, freq, next_freq, position)
+                } else {
+                    freq
+                };
+
+                audio_samples.push(self.next_sample(instant_freq));
+                self.bit_clock += self.bit_step;
+            }
+            self.bit_clock -= 1.0;
+        }
+
+        audio_samples
+    }
+
+    /// Advances the oscillator by one sample at the given frequency
+    /// Returns the sample scaled to the configured amplitude
+    fn next_sample(&mut self, freq: f32) -> f32 {
+        let sample = self.phase.sin() * self.amplitude;
+
+        // Keep the phase wrapped so it doesn't lose precision on long transmissions
+        self.phase += 2.0 * PI * freq / self.sample_rate;
+        if self.phase >= 2.0 * PI {
+            self.phase -= 2.0 * PI;
+        }
+
+        sample
+    }
+}
+
+/// Maps a tone to its Bell 202 frequency
+fn tone_freq(mark: bool) -> f32 {
+    if mark { MARK_FREQ } else { SPACE_FREQ }
+}
+
+/// Computes the instantaneous frequency near a bit boundary when shaping is on
+/// Returns a raised cosine blend between neighbouring tones, half way at the boundary itself
+fn shaped_freq(previous_freq: f32, freq: f32, next_freq: f32, position: f32) -> f32 {
+    let raised_cosine = |x: f32| 0.5 * (1.0 - (PI * x).cos());
+
+    if position < SHAPING_SPAN {
+        // Finishing the glide that started at the end of the previous bit
+        let x = 0.5 + position / (2.0 * SHAPING_SPAN);
+        previous_freq + (freq - previous_freq) * raised_cosine(x)
+    } else if position > 1.0 - SHAPING_SPAN {
+        // Starting the glide towards the next bit
+        let x = (position - (1.0 - SHAPING_SPAN)) / (2.0 * SHAPING_SPAN);
+        freq + (next_freq - freq) * raised_cosine(x)
+    } else {
+        freq
+    }
+}
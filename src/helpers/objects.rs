@@ -0,0 +1,207 @@
+use crate::helpers::constants::MAX_TRANSMISSIONS;
+use crate::helpers::errors::CodecError;
+use crate::helpers::{aprs, position};
+use crate::models::aprs_data::{AprsData, AprsObject};
+use crate::models::aprs_packet::AprsPacket;
+use crate::models::tracked_object::{ObjectRequest, TrackedObject};
+use std::time::{Duration, Instant};
+
+// Objects and items put something other than the sending station on the map:
+// - Objects are ";NAME_____*DDHHMMzPOSITION", with the name padded with spaces to 9 characters,
+//   '*' when live or '_' when killed, and a timestamp that is always there
+// - Items are ")NAME!POSITION", with a name of 3 to 9 characters ended by '!' when live or
+//   '_' when killed, and no timestamp
+// The position, uncompressed or compressed and followed by the comment, is the same as in a
+// position report and is read and written by the position module.
+//
+// Stations forget objects they haven't heard about in a while, so the object store beacons
+// every live object again at a fixed interval. A killed object is beaconed a few more times,
+// so stations that missed the first kill take it off the map too, then dropped.
+
+const OBJECT_NAME_LEN: usize = 9;
+const MIN_ITEM_NAME_LEN: usize = 3;
+const TIMESTAMP_LEN: usize = 7;
+
+/// Reads an object out of an information field starting with ';'
+/// Returns None if the name isn't padded to 9 characters or the timestamp or position is malformed
+pub fn parse_object(information: &str) -> Option<AprsObject> {
+    let body = information.strip_prefix(';')?;
+    let name = body.get(..OBJECT_NAME_LEN)?.trim_end().to_string();
+    let live = parse_flag(body.get(OBJECT_NAME_LEN..)?.chars().next()?, '*')?;
+    if name.is_empty() {
+        return None;
+    }
+
+    let rest = body.get(OBJECT_NAME_LEN + 1..)?;
+    let timestamp = aprs::parse_timestamp(rest.get(..TIMESTAMP_LEN)?)?;
+    let mut position = position::parse_position(rest.get(TIMESTAMP_LEN..)?)?;
+    position.timestamp = Some(timestamp);
+
+    Some(AprsObject { name, live, position })
+}
+
+/// Reads an item out of an information field starting with ')'
+/// Returns None if the name isn't 3 to 9 characters or the position is malformed
+pub fn parse_item(information: &str) -> Option<AprsObject> {
+    let body = information.strip_prefix(')')?;
+    let end = body.find(['!', '_'])?;
+    if !(MIN_ITEM_NAME_LEN..=OBJECT_NAME_LEN).contains(&end) {
+        return None;
+    }
+
+    let live = parse_flag(body[end..].chars().next()?, '!')?;
+    let position = position::parse_position(&body[end + 1..])?;
+
+    Some(AprsObject { name: body[..end].to_string(), live, position })
+}
+
+/// Builds the information field of an object, or of an item
+/// Returns the field, or why it can't be sent
+pub fn encode_object(object: &AprsObject, item: bool) -> Result<String, CodecError> {
+    let invalid = |reason: &str| Err(CodecError::InvalidAprsData(reason.to_string()));
+    let printable = object.name.bytes().all(|b| (b' '..=b'~').contains(&b));
+
+    let mut information = if item {
+        if !printable || !(MIN_ITEM_NAME_LEN..=OBJECT_NAME_LEN).contains(&object.name.len()) || object.name.contains(['!', '_']) {
+            return invalid("item names must be 3 to 9 printable characters, without '!' or '_'");
+        }
+        if object.position.timestamp.is_some() {
+            return invalid("items don't carry a timestamp");
+        }
+        format!("){}{}", object.name, if object.live { '!' } else { '_' })
+    } else {
+        if !printable || object.name.trim_end().is_empty() || object.name.len() > OBJECT_NAME_LEN {
+            return invalid("object names must be 1 to 9 printable characters");
+        }
+        let Some(timestamp) = object.position.timestamp else {
+            return invalid("objects need a timestamp");
+        };
+        if !timestamp.is_valid() {
+            return invalid("the timestamp is out of range");
+        }
+        format!(";{:<width$}{}{}", object.name, if object.live { '*' } else { '_' }, timestamp, width = OBJECT_NAME_LEN)
+    };
+
+    information.push_str(&position::encode_position(&object.position)?);
+    Ok(information)
+}
+
+/// Reads the live or killed flag, live being the one given
+fn parse_flag(flag: char, live: char) -> Option<bool> {
+    match flag {
+        '_' => Some(false),
+        flag if flag == live => Some(true),
+        _ => None,
+    }
+}
+
+/// How often the object store beacons its objects
+#[derive(Clone, Debug)]
+pub struct BeaconPolicy {
+    pub interval: Duration,
+    pub kill_beacons: u32,
+}
+
+impl Default for BeaconPolicy {
+    fn default() -> Self {
+        BeaconPolicy {
+            interval: Duration::from_secs(10 * 60),
+            kill_beacons: 3,
+        }
+    }
+}
+
+/// Keeps the objects and items we put on the map, and beacons them until they're killed
+/// Time is passed in by the caller, the store never looks at the clock itself
+#[derive(Default)]
+pub struct ObjectStore {
+    policy: BeaconPolicy,
+    objects: Vec<TrackedObject>,
+}
+
+impl ObjectStore {
+    pub fn new(policy: BeaconPolicy) -> Self {
+        ObjectStore { policy, objects: Vec::new() }
+    }
+
+    /// Creates an object, or replaces the one with the same name, its next beacon is due right away
+    /// Returns the object as the store keeps it, or why it can't be sent
+    pub fn set(&mut self, request: ObjectRequest, now: Instant) -> Result<TrackedObject, CodecError> {
+        let object = TrackedObject {
+            source: request.source,
+            destination: request.destination,
+            digipeaters: request.digipeaters,
+            item: request.item,
+            object: request.object,
+            beacons: 0,
+            next_beacon: Some(now),
+        };
+
+        // Build the frame once, so a bad callsign or position fails now and not at the next beacon
+        let information = encode_object(&object.object, object.item)?;
+        AprsPacket::new(&object.source, &object.destination, &object.digipeaters, &information).encode()?;
+
+        match self.objects.iter_mut().find(|o| o.object.name == object.object.name) {
+            Some(existing) => *existing = object.clone(),
+            None => self.objects.push(object.clone()),
+        }
+        Ok(object)
+    }
+
+    /// Kills an object, it's beaconed as killed right away and a few more times before being dropped
+    /// Returns the killed object, or None if the store has none by that name
+    pub fn kill(&mut self, name: &str, now: Instant) -> Option<TrackedObject> {
+        let object = self.objects.iter_mut().find(|o| o.object.name == name)?;
+        object.object.live = false;
+        object.beacons = 0;
+        object.next_beacon = Some(now);
+        Some(object.clone())
+    }
+
+    /// Takes the objects due for a beacon, and drops the killed ones beaconed often enough
+    /// Returns the packets to send now, in the order the objects were created, as many as fit
+    /// in one recording. The others stay due for the next call
+    pub fn due(&mut self, now: Instant) -> Vec<AprsPacket> {
+        let mut packets = Vec::new();
+
+        for object in &mut self.objects {
+            if packets.len() == MAX_TRANSMISSIONS {
+                break;
+            }
+            if object.next_beacon.is_none_or(|t| t > now) {
+                continue;
+            }
+
+            object.beacons += 1;
+            object.next_beacon = Some(now + self.policy.interval);
+            packets.push(packet_for(object));
+        }
+
+        let kill_beacons = self.policy.kill_beacons;
+        self.objects.retain(|o| o.object.live || o.beacons < kill_beacons);
+        packets
+    }
+
+    /// When the next beacon is due, if the store has any object
+    pub fn next_due(&self) -> Option<Instant> {
+        self.objects.iter().filter_map(|o| o.next_beacon).min()
+    }
+
+    /// Every object still beaconed, live or killed
+    pub fn objects(&self) -> &[TrackedObject] {
+        &self.objects
+    }
+}
+
+/// The packet an object is beaconed in, its information field is built from the APRS data
+fn packet_for(object: &TrackedObject) -> AprsPacket {
+    let data = if object.item {
+        AprsData::Item(object.object.clone())
+    } else {
+        AprsData::Object(object.object.clone())
+    };
+
+    let mut packet = AprsPacket::new(&object.source, &object.destination, &object.digipeaters, "");
+    packet.aprs = Some(data);
+    packet
+}
@@ -0,0 +1,335 @@
+use crate::helpers::errors::CodecError;
+use crate::models::aprs_data::{CompressionType, NmeaSource, Position};
+
+// Position reports come in two layouts, told apart by the first character of the position:
+// a digit (or a space hiding one) starts an uncompressed position, anything else is the
+// symbol table of a compressed one.
+//
+// Uncompressed, fixed width: "4903.50N/07201.75W-" is the latitude, the symbol table, the
+// longitude and the symbol code. A station can hide its exact location by replacing digits
+// with spaces from the right, the number of hidden digits is the position ambiguity.
+// The comment may start with a data extension, "088/036" for course and speed in knots or
+// "RNG0050" for the radio range in miles.
+//
+// Compressed, always 13 characters: "/5L!!<*e7>7P[" is the symbol table, the latitude and
+// the longitude as 4 base-91 digits each, the symbol code, then the two "cs" bytes holding
+// the course and speed, the altitude or the radio range, and the compression type byte.
+// A space in place of the first cs byte means none of them was sent. Overlays on the
+// symbol table are sent as 'a' to 'j' instead of digits, so they can't be mistaken for
+// the start of an uncompressed latitude. Compressed beacons spend 6 fewer bytes of airtime
+// on the position, more once course and speed or altitude are included.
+//
+// Either layout can carry the altitude anywhere in the comment as "/A=001234", in feet.
+
+/// Base-91 digits are sent as the printable characters from '!' (0) to '{' (90)
+const BASE91_OFFSET: u8 = 33;
+/// Base-91 units per degree of latitude, 90 degrees north is 0 and 90 south is 91^4 - 1
+const LATITUDE_SCALE: f64 = 380926.0;
+/// Base-91 units per degree of longitude, 180 degrees west is 0 and 180 east is 91^4 - 1
+const LONGITUDE_SCALE: f64 = 190463.0;
+/// First cs byte, once decoded, saying the second one holds the radio range
+const RANGE_MARKER: u8 = 90;
+/// Course, speed and range are powers of this, one step per base-91 digit
+const SPEED_BASE: f64 = 1.08;
+/// Altitude is a power of this, over both cs bytes
+const ALTITUDE_BASE: f64 = 1.002;
+
+/// Reads a position report, uncompressed or compressed, up to the end of its comment
+/// Returns None if any of the fixed width fields is malformed
+pub fn parse_position(body: &str) -> Option<Position> {
+    let first = body.chars().next()?;
+    let mut position = if first.is_ascii_digit() || first == ' ' {
+        parse_uncompressed(body)?
+    } else {
+        parse_compressed(body)?
+    };
+
+    if position.altitude.is_none() {
+        position.altitude = take_altitude(&mut position.comment);
+    }
+
+    Some(position)
+}
+
+/// Builds a position report in the layout it asks for, comment included
+/// Returns what follows the data type identifier and timestamp, or why the position can't be sent
+pub fn encode_position(position: &Position) -> Result<String, CodecError> {
+    validate(position)?;
+
+    let mut body = String::new();
+    let altitude_sent = if position.compressed {
+        encode_compressed(position, &mut body)
+    } else {
+        encode_uncompressed(position, &mut body);
+        false
+    };
+
+    if let Some(altitude) = position.altitude.filter(|_| !altitude_sent) {
+        body.push_str(&format!("/A={:06}", altitude.round() as i32));
+    }
+    body.push_str(&position.comment);
+
+    Ok(body)
+}
+
+/// Checks a position can be sent as it is
+fn validate(position: &Position) -> Result<(), CodecError> {
+    let invalid = |reason: &str| Err(CodecError::InvalidAprsData(reason.to_string()));
+
+    if !(-90.0..=90.0).contains(&position.latitude) || !(-180.0..=180.0).contains(&position.longitude) {
+        return invalid("latitude must be within 90 degrees and longitude within 180 degrees");
+    }
+    if !valid_symbol_table(position.symbol_table) || !position.symbol_code.is_ascii_graphic() {
+        return invalid("symbol table must be '/', '\\' or an overlay digit or capital letter, and the symbol code printable");
+    }
+    if position.ambiguity > 4 || (position.compressed && position.ambiguity > 0) {
+        return invalid("ambiguity hides at most 4 digits, and only in uncompressed positions");
+    }
+    if position.timestamp.is_some_and(|t| !t.is_valid()) {
+        return invalid("timestamp out of range");
+    }
+    if position.course.is_some() != position.speed.is_some() {
+        return invalid("course and speed are sent together");
+    }
+    // Speed and range go out rounded to 3 and 4 digits in the uncompressed data extensions
+    if position.course.is_some_and(|c| c > 360) || position.speed.is_some_and(|s| s < 0.0 || s.round() > 999.0) {
+        return invalid("course must be from 0 to 360 degrees and speed at most 999 knots");
+    }
+    if position.altitude.is_some_and(|a| !(-99999.0..=999999.0).contains(&a)) {
+        return invalid("altitude must fit in 6 digits of feet");
+    }
+    if position.range.is_some_and(|r| r < 0.0 || r.round() > 9999.0) {
+        return invalid("range must be at most 9999 miles");
+    }
+    if position.range.is_some() && (position.speed.is_some() || (position.compressed && position.altitude.is_some())) {
+        return invalid("range can't be sent along with course and speed, or with altitude in a compressed position");
+    }
+    if position.compressed && position.speed.is_some()
+        && position.compression_type.is_some_and(|t| t.nmea_source == NmeaSource::Gga) {
+        return invalid("a GGA compression type means the cs bytes hold altitude, not course and speed");
+    }
+
+    Ok(())
+}
+
+/// Reads an uncompressed position: latitude, symbol table, longitude, symbol code, then the comment
+fn parse_uncompressed(body: &str) -> Option<Position> {
+    let (latitude, ambiguity) = parse_coordinate(body.get(..8)?, 2, ('N', 'S'), 90.0)?;
+    let symbol_table = single_char(body.get(8..9)?);
+    let (longitude, _) = parse_coordinate(body.get(9..18)?, 3, ('E', 'W'), 180.0)?;
+    let symbol_code = single_char(body.get(18..19)?);
+
+    if !valid_symbol_table(symbol_table) || !symbol_code.is_ascii_graphic() {
+        return None;
+    }
+
+    let mut position = Position { latitude, longitude, ambiguity, symbol_table, symbol_code, ..Position::default() };
+    position.comment = parse_extension(body.get(19..)?, &mut position).to_string();
+    Some(position)
+}
+
+/// Reads an uncompressed coordinate, DDMM.mmH for a latitude or DDDMM.mmH for a longitude
+/// Returns it in signed decimal degrees, along with how many digits were hidden by spaces
+fn parse_coordinate(field: &str, degree_digits: usize, hemispheres: (char, char), max: f64) -> Option<(f64, u8)> {
+    let (number, hemisphere) = field.split_at_checked(field.len().checked_sub(1)?)?;
+    let sign = match single_char(hemisphere) {
+        h if h == hemispheres.0 => 1.0,
+        h if h == hemispheres.1 => -1.0,
+        _ => return None,
+    };
+
+    let (whole, hundredths) = number.split_once('.')?;
+    if whole.len() != degree_digits + 2 || hundredths.len() != 2 {
+        return None;
+    }
+    let digits: Vec<u8> = whole.bytes().chain(hundredths.bytes()).collect();
+
+    // Hidden digits are spaces at the end, at most the four minute digits, and count as 0
+    let ambiguity = digits.iter().rev().take_while(|&&d| d == b' ').count();
+    if ambiguity > 4 || !digits[..digits.len() - ambiguity].iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let value = |digits: &[u8]| digits.iter().fold(0u32, |acc, &d| acc * 10 + d.saturating_sub(b'0') as u32);
+
+    let degrees = value(&digits[..degree_digits]) as f64;
+    let minutes = value(&digits[degree_digits..]) as f64 / 100.0;
+    if minutes >= 60.0 || degrees + minutes / 60.0 > max {
+        return None;
+    }
+
+    Some((sign * (degrees + minutes / 60.0), ambiguity as u8))
+}
+
+/// Reads the data extension at the start of an uncompressed position's comment into the position
+/// Returns the rest of the comment
+fn parse_extension<'a>(comment: &'a str, position: &mut Position) -> &'a str {
+    let number = |field: Option<&str>| field
+        .filter(|f| f.bytes().all(|d| d.is_ascii_digit()))
+        .and_then(|f| f.parse::<u16>().ok());
+
+    if comment.get(3..4) == Some("/") {
+        if let (Some(course @ 0..=360), Some(speed)) = (number(comment.get(..3)), number(comment.get(4..7))) {
+            position.course = Some(course);
+            position.speed = Some(speed as f64);
+            return &comment[7..];
+        }
+    }
+    if let (Some("RNG"), Some(range)) = (comment.get(..3), number(comment.get(3..7))) {
+        position.range = Some(range as f64);
+        return &comment[7..];
+    }
+
+    comment
+}
+
+/// Writes an uncompressed position and its data extension
+fn encode_uncompressed(position: &Position, body: &mut String) {
+    body.push_str(&format_coordinate(position.latitude, 2, ('N', 'S'), position.ambiguity));
+    body.push(position.symbol_table);
+    body.push_str(&format_coordinate(position.longitude, 3, ('E', 'W'), position.ambiguity));
+    body.push(position.symbol_code);
+
+    if let (Some(course), Some(speed)) = (position.course, position.speed) {
+        body.push_str(&format!("{:03}/{:03}", course, speed.round() as u16));
+    } else if let Some(range) = position.range {
+        body.push_str(&format!("RNG{:04}", range.round() as u16));
+    }
+}
+
+/// Formats a coordinate as DDMM.mmH or DDDMM.mmH, hiding `ambiguity` digits from the right
+fn format_coordinate(value: f64, degree_digits: usize, hemispheres: (char, char), ambiguity: u8) -> String {
+    let hemisphere = if value < 0.0 { hemispheres.1 } else { hemispheres.0 };
+
+    // Rounded to hundredths of a minute first, so 59.999 minutes carries into the degrees
+    let hundredths = (value.abs() * 6000.0).round() as u32;
+    let digits = format!("{:0width$}{:04}", hundredths / 6000, hundredths % 6000, width = degree_digits);
+
+    let visible = digits.len() - ambiguity as usize;
+    let mut field: String = digits[..visible].chars().chain(std::iter::repeat_n(' ', ambiguity as usize)).collect();
+    field.insert(degree_digits + 2, '.');
+    field.push(hemisphere);
+    field
+}
+
+/// Reads a compressed position, its cs bytes and compression type, then the comment
+fn parse_compressed(body: &str) -> Option<Position> {
+    let field = body.get(..13)?.as_bytes();
+
+    let symbol_table = match field[0] {
+        overlay @ b'a'..=b'j' => (overlay - b'a' + b'0') as char,
+        table => table as char,
+    };
+    let symbol_code = field[9] as char;
+    if !valid_symbol_table(symbol_table) || !symbol_code.is_ascii_graphic() {
+        return None;
+    }
+
+    let latitude = 90.0 - base91_decode(&field[1..5])? as f64 / LATITUDE_SCALE;
+    let longitude = base91_decode(&field[5..9])? as f64 / LONGITUDE_SCALE - 180.0;
+    if latitude < -90.0 || longitude > 180.0 {
+        return None;
+    }
+
+    let mut position = Position { latitude, longitude, symbol_table, symbol_code, compressed: true, ..Position::default() };
+
+    if field[10] != b' ' {
+        let (c, s) = (base91_digit(field[10])?, base91_digit(field[11])?);
+        let compression_type = CompressionType::from_bits(base91_digit(field[12])? & 0x3F);
+
+        if compression_type.nmea_source == NmeaSource::Gga {
+            position.altitude = Some(ALTITUDE_BASE.powi(c as i32 * 91 + s as i32));
+        } else if c == RANGE_MARKER {
+            position.range = Some(2.0 * SPEED_BASE.powi(s as i32));
+        } else {
+            position.course = Some(c as u16 * 4);
+            position.speed = Some(SPEED_BASE.powi(s as i32) - 1.0);
+        }
+        position.compression_type = Some(compression_type);
+    }
+
+    position.comment = body.get(13..)?.to_string();
+    Some(position)
+}
+
+/// Writes a compressed position, packing course and speed, altitude or range into the cs bytes
+/// Returns whether the altitude went into the cs bytes, otherwise it still has to go in the comment
+fn encode_compressed(position: &Position, body: &mut String) -> bool {
+    body.push(match position.symbol_table {
+        overlay @ '0'..='9' => (overlay as u8 - b'0' + b'a') as char,
+        table => table,
+    });
+    base91_encode(((90.0 - position.latitude) * LATITUDE_SCALE) as u32, 4, body);
+    base91_encode(((180.0 + position.longitude) * LONGITUDE_SCALE) as u32, 4, body);
+    body.push(position.symbol_code);
+
+    let mut compression_type = position.compression_type.unwrap_or_default();
+    let log = |value: f64, base: f64| (value.max(1.0).ln() / base.ln()).round() as u32;
+
+    let cs = if let (Some(course), Some(speed)) = (position.course, position.speed) {
+        Some(((course as f64 / 4.0).round() as u32 % 90, log(speed + 1.0, SPEED_BASE).min(89)))
+    } else if let Some(altitude) = position.altitude.filter(|&a| a >= 1.0) {
+        // Only a GGA compression type tells the receiver the cs bytes are an altitude,
+        // which can't go below 1 foot there, lower ones are left to the comment
+        compression_type.nmea_source = NmeaSource::Gga;
+        let value = log(altitude, ALTITUDE_BASE).min(91 * 91 - 1);
+        Some((value / 91, value % 91))
+    } else {
+        position.range.map(|range| (RANGE_MARKER as u32, log(range / 2.0, SPEED_BASE).min(90)))
+    };
+
+    match cs {
+        Some((c, s)) => {
+            body.push(base91_char(c));
+            body.push(base91_char(s));
+        }
+        None => body.push_str("  "),
+    }
+    body.push(base91_char(compression_type.to_bits() as u32));
+
+    cs.is_some() && position.speed.is_none() && position.altitude.is_some()
+}
+
+/// Reads a base-91 number, most significant digit first
+pub fn base91_decode(digits: &[u8]) -> Option<u32> {
+    digits.iter().try_fold(0u32, |acc, &d| Some(acc * 91 + base91_digit(d)? as u32))
+}
+
+/// Writes a number as `width` base-91 digits, most significant first, capped to the largest that fits
+pub fn base91_encode(value: u32, width: u32, body: &mut String) {
+    let value = value.min(91u32.pow(width) - 1);
+    for power in (0..width).rev() {
+        body.push(base91_char(value / 91u32.pow(power) % 91));
+    }
+}
+
+/// Reads one base-91 digit
+fn base91_digit(byte: u8) -> Option<u8> {
+    byte.checked_sub(BASE91_OFFSET).filter(|&d| d <= 90)
+}
+
+/// Writes one base-91 digit
+fn base91_char(digit: u32) -> char {
+    (digit as u8 + BASE91_OFFSET) as char
+}
+
+/// Finds the altitude extension anywhere in a comment and takes it out
+/// Returns the altitude in feet, if the comment had one
+fn take_altitude(comment: &mut String) -> Option<f64> {
+    let start = comment.find("/A=")?;
+    let digits = comment.get(start + 3..start + 9)?;
+    let valid = digits.bytes().enumerate().all(|(i, d)| d.is_ascii_digit() || (i == 0 && d == b'-'));
+    let altitude = digits.parse::<i32>().ok().filter(|_| valid)?;
+
+    comment.replace_range(start..start + 9, "");
+    Some(altitude as f64)
+}
+
+/// Overlays are digits and capital letters drawn over the alternate table
+pub fn valid_symbol_table(table: char) -> bool {
+    matches!(table, '/' | '\\' | '0'..='9' | 'A'..='Z')
+}
+
+/// Takes the only character of a one character string
+fn single_char(field: &str) -> char {
+    field.chars().next().unwrap_or_default()
+}
@@ -0,0 +1,49 @@
+use crate::helpers::filter::FirFilter;
+
+// Sample rate conversion for recordings that don't come at the rate the decoder runs at.
+// Going down, a low-pass filter first removes everything above the new Nyquist frequency
+// so it can't fold back onto the tones. Then every output sample is interpolated
+// linearly between the two input samples around it, which is plenty for 1200 baud
+// tones sitting far below either Nyquist frequency.
+
+/// Taps of the anti-aliasing filter for every unit of the decimation ratio
+const TAPS_PER_RATIO: f64 = 32.0;
+
+/// Converts a whole recording from one sample rate to another
+/// Returns the samples at the new rate, the same duration as the input
+pub fn resample(samples: &[i16], from: u32, to: u32) -> Vec<i16> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let ratio = from as f64 / to as f64;
+    let input = samples.iter().map(|&s| s as f32);
+
+    let filtered: Vec<f32> = if to < from {
+        let len = (TAPS_PER_RATIO * ratio) as usize | 1;
+        let mut filter = FirFilter::low_pass(0.45 * to as f32, from, len);
+
+        // The filter delays the signal by half its length, run it that much longer and
+        // drop the start so the output stays aligned with the input
+        let delay = len / 2;
+        input.chain(std::iter::repeat_n(0.0, delay))
+            .map(|s| filter.process(s))
+            .skip(delay)
+            .collect()
+    } else {
+        input.collect()
+    };
+
+    let output_len = (samples.len() as u64 * to as u64 / from as u64) as usize;
+    (0..output_len)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position as usize;
+            let fraction = (position - index as f64) as f32;
+
+            let current = filtered[index];
+            let next = filtered.get(index + 1).copied().unwrap_or(current);
+            (current + (next - current) * fraction).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+        })
+        .collect()
+}
@@ -0,0 +1,208 @@
+use super::constants::{MAX_PAYLOAD_SIZE, MAX_SEGMENTS};
+use crate::helpers::errors::CodecError;
+use crate::models::decoded_frame::{CrcStatus, DecodedFrame};
+use crate::models::reassembled_message::{ReassembledMessage, SegmentState, SegmentStatus};
+use crc::{Crc, CRC_16_IBM_SDLC};
+use std::fmt;
+
+// Messages longer than one information field are sent as several frames, each one
+// starting with a segment header: {ID:INDEX:COUNT}
+// - ID: 4 hex digits identifying the message, so segments of different messages from
+//   the same station don't get mixed up
+// - INDEX: position of the segment, from 1
+// - COUNT: how many segments the message was split into
+// For example "{3F2A:2:3}" is the second of three segments of message 3F2A.
+// Messages that fit in a single frame are sent as they are, without a header.
+
+/// Longest header there can be, "{XXXX:255:255}", kept free in every segment
+const MAX_HEADER_LEN: usize = 14;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SegmentHeader {
+    pub message_id: String,
+    pub index: usize,
+    pub count: usize,
+}
+
+impl SegmentHeader {
+    /// Reads the segment header at the start of an information field
+    /// Returns the header and the segment text after it, or None if the field doesn't start with one
+    pub fn parse(information: &str) -> Option<(SegmentHeader, &str)> {
+        let (header, text) = information.strip_prefix('{')?.split_once('}')?;
+        let mut fields = header.split(':');
+
+        let message_id = fields.next()?;
+        let index = fields.next()?.parse::<usize>().ok()?;
+        let count = fields.next()?.parse::<usize>().ok()?;
+        if fields.next().is_some() {
+            return None;
+        }
+
+        let valid_id = message_id.len() == 4 && message_id.chars().all(|c| c.is_ascii_hexdigit());
+        if !valid_id || index == 0 || index > count || count > MAX_SEGMENTS {
+            return None;
+        }
+
+        Some((SegmentHeader { message_id: message_id.to_string(), index, count }, text))
+    }
+}
+
+impl fmt::Display for SegmentHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{{}:{}:{}}}", self.message_id, self.index, self.count)
+    }
+}
+
+/// Splits a message into the information fields of the frames carrying it
+/// Returns the message as is if it fits in one frame, otherwise one header prefixed segment per frame
+pub fn split_message(information: &str) -> Result<Vec<String>, CodecError> {
+    if information.len() <= MAX_PAYLOAD_SIZE {
+        return Ok(vec![information.to_string()]);
+    }
+
+    let chunks = split_at_char_boundaries(information, MAX_PAYLOAD_SIZE - MAX_HEADER_LEN);
+    if chunks.len() > MAX_SEGMENTS {
+        return Err(CodecError::MessageTooLong { length: information.len(), max: MAX_SEGMENTS * (MAX_PAYLOAD_SIZE - MAX_HEADER_LEN) });
+    }
+
+    // Derived from the text, so sending the same message again gives the same ID
+    let message_id = format!("{:04X}", Crc::<u16>::new(&CRC_16_IBM_SDLC).checksum(information.as_bytes()));
+
+    Ok(chunks.iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let header = SegmentHeader { message_id: message_id.clone(), index: i + 1, count: chunks.len() };
+            format!("{}{}", header, chunk)
+        })
+        .collect())
+}
+
+/// Cuts a string into pieces of at most `max_len` bytes without splitting a character
+fn split_at_char_boundaries(text: &str, max_len: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let mut end = max_len.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, remaining) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = remaining;
+    }
+
+    chunks
+}
+
+/// Collects segments from decoded frames and puts their messages back together
+/// Frames without a segment header are ignored, they already carry a whole message
+#[derive(Default)]
+pub struct Reassembler {
+    messages: Vec<PartialMessage>,
+}
+
+/// A message whose segments are still being collected
+struct PartialMessage {
+    source: String,
+    destination: String,
+    header: SegmentHeader,
+    segments: Vec<Option<(String, SegmentStatus)>>,
+}
+
+impl Reassembler {
+    pub fn new() -> Self {
+        Reassembler { messages: Vec::new() }
+    }
+
+    /// Adds a decoded frame to the message it belongs to
+    /// Returns the message as soon as its last missing segment arrives
+    pub fn push(&mut self, frame: &DecodedFrame) -> Option<ReassembledMessage> {
+        let packet = &frame.packet;
+        let (header, text) = SegmentHeader::parse(&packet.information)?;
+
+        // Same sender, same ID and same segment count make the same message
+        let position = self.messages.iter().position(|m| {
+            m.source == packet.source && m.destination == packet.destination
+                && m.header.message_id == header.message_id && m.header.count == header.count
+        });
+        let position = position.unwrap_or_else(|| {
+            self.messages.push(PartialMessage {
+                source: packet.source.clone(),
+                destination: packet.destination.clone(),
+                header: header.clone(),
+                segments: vec![None; header.count],
+            });
+            self.messages.len() - 1
+        });
+
+        // A repeat only replaces a segment when its FCS did better, so a corrupted copy heard
+        // first doesn't stand in for the good one a repeat brings
+        let message = &mut self.messages[position];
+        let segment = &mut message.segments[header.index - 1];
+        if segment.as_ref().is_some_and(|(_, status)| crc_rank(status.crc) >= crc_rank(Some(frame.crc))) {
+            return None;
+        }
+        let status = SegmentStatus { index: header.index, status: SegmentState::Received, crc: Some(frame.crc) };
+        *segment = Some((text.to_string(), status));
+
+        // Segments that failed their FCS keep the message waiting for a better copy
+        if message.segments.iter().all(|s| s.as_ref().is_some_and(|(_, status)| status.crc != Some(CrcStatus::Invalid))) {
+            return Some(self.messages.remove(position).into_message());
+        }
+
+        None
+    }
+
+    /// Gives up waiting for missing segments, at the end of a recording or a stream
+    /// Returns every message still incomplete, in the order their first segment arrived
+    pub fn finish(self) -> Vec<ReassembledMessage> {
+        self.messages.into_iter().map(PartialMessage::into_message).collect()
+    }
+}
+
+impl PartialMessage {
+    fn into_message(self) -> ReassembledMessage {
+        let mut text = String::new();
+        let mut segments = Vec::with_capacity(self.segments.len());
+
+        for (i, segment) in self.segments.into_iter().enumerate() {
+            match segment {
+                Some((segment_text, status)) => {
+                    text.push_str(&segment_text);
+                    segments.push(status);
+                }
+                None => segments.push(SegmentStatus { index: i + 1, status: SegmentState::Missing, crc: None }),
+            }
+        }
+
+        ReassembledMessage {
+            source: self.source,
+            destination: self.destination,
+            message_id: self.header.message_id,
+            complete: segments.iter().all(|s| s.status == SegmentState::Received && s.crc != Some(CrcStatus::Invalid)),
+            text,
+            segments,
+        }
+    }
+}
+
+/// Orders CRC statuses from worst to best, an intact frame beats a repaired one
+fn crc_rank(crc: Option<CrcStatus>) -> u8 {
+    match crc {
+        Some(CrcStatus::Valid) => 2,
+        Some(CrcStatus::Corrected) => 1,
+        _ => 0,
+    }
+}
+
+/// Puts back together every segmented message in a list of decoded frames
+/// Returns complete messages first, in the order they were completed, then incomplete ones
+pub fn reassemble(frames: &[DecodedFrame]) -> Vec<ReassembledMessage> {
+    let mut reassembler = Reassembler::new();
+    let mut messages: Vec<ReassembledMessage> = frames.iter()
+        .filter_map(|frame| reassembler.push(frame))
+        .collect();
+    messages.extend(reassembler.finish());
+    messages
+}
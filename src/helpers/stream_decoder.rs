@@ -0,0 +1,196 @@
+use super::constants::BAUD_RATE;
+use crate::helpers::clock_recovery::ClockRecovery;
+use crate::helpers::decoder::decode_frame;
+use crate::helpers::demodulator::{new_demodulator, Demodulator, DemodulatorVariant, VARIANTS};
+use crate::helpers::hdlc::{HdlcDecoder, HdlcEvent};
+use crate::models::decode_options::DecodeOptions;
+use crate::models::decoded_frame::DecodedFrame;
+use log::debug;
+
+// Incremental decoder for live audio, from a pipe, a socket or an SDR tool such as rtl_fm.
+// Samples are pushed in blocks of any size as they arrive, every demodulator variant
+// keeps its demodulator, PLL and HDLC state from one block to the next, and frames are
+// handed to a callback as soon as no other variant can still report the same frame.
+// Variants get a thread each only for blocks big enough to be worth spawning threads for,
+// small blocks as read from a pipe go through them one after the other.
+
+// A tenth of a second at 48 kHz, spawning the threads costs more than that much audio below it
+const MIN_PARALLEL_BLOCK: usize = 4800;
+
+pub struct StreamDecoder<F: FnMut(DecodedFrame)> {
+    channels: Vec<Channel>,
+    options: DecodeOptions,
+    sample_rate: u32,
+    samples_seen: usize,
+    merge_window: usize,
+    pending: Vec<ReceivedFrame>,
+    on_frame: F,
+}
+
+impl<F: FnMut(DecodedFrame)> StreamDecoder<F> {
+    pub fn new(sample_rate: u32, options: &DecodeOptions, on_frame: F) -> Self {
+        let variants = if options.parallel { &VARIANTS[..] } else { &VARIANTS[..1] };
+        debug!("Running {} demodulator variant(s)", variants.len());
+
+        StreamDecoder {
+            channels: variants.iter().map(|variant| Channel::new(sample_rate, options, variant)).collect(),
+            options: options.clone(),
+            sample_rate,
+            samples_seen: 0,
+            // Copies of the same frame from different variants end within a byte of each other
+            merge_window: (8.0 * sample_rate as f32 / BAUD_RATE) as usize,
+            pending: Vec::new(),
+            on_frame,
+        }
+    }
+
+    /// Feeds the next block of samples into every variant
+    /// Frames completed in this block are passed to the callback once they can no longer get duplicates
+    pub fn push_samples(&mut self, block: &[i16]) {
+        let first_sample = self.samples_seen;
+        let sample_rate = self.sample_rate;
+        let options = &self.options;
+
+        // Run every variant over the same block, on its own thread if the block is big enough
+        let received: Vec<ReceivedFrame> = if self.channels.len() == 1 || block.len() < MIN_PARALLEL_BLOCK {
+            self.channels.iter_mut()
+                .flat_map(|channel| channel.process(block, first_sample, sample_rate, options))
+                .collect()
+        } else {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = self.channels.iter_mut()
+                    .map(|channel| scope.spawn(move || channel.process(block, first_sample, sample_rate, options)))
+                    .collect();
+
+                handles.into_iter()
+                    .flat_map(|handle| handle.join().unwrap())
+                    .collect()
+            })
+        };
+
+        self.samples_seen += block.len();
+        for frame in received {
+            self.merge(frame);
+        }
+
+        // Hand over the frames no variant can report a copy of anymore
+        let horizon = self.samples_seen.saturating_sub(self.merge_window);
+        let (ready, waiting) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|r| r.end_sample < horizon);
+        self.pending = waiting;
+        self.emit(ready);
+    }
+
+    /// Tells whether any variant has heard an HDLC flag so far, valid frame or not
+    pub fn heard_flag(&self) -> bool {
+        self.channels.iter().any(|channel| channel.heard_flag)
+    }
+
+    /// Hands every frame still waiting for duplicates to the callback, at the end of the stream
+    pub fn flush(&mut self) {
+        let ready = std::mem::take(&mut self.pending);
+        self.emit(ready);
+    }
+
+    /// Merges a frame into the pending ones if another variant already reported it
+    fn merge(&mut self, candidate: ReceivedFrame) {
+        let merge_window = self.merge_window;
+        let duplicate = self.pending.iter_mut().find(|p| {
+            p.frame.fcs == candidate.frame.fcs && p.end_sample.abs_diff(candidate.end_sample) <= merge_window
+        });
+
+        match duplicate {
+            Some(existing) => {
+                let mut decoders = std::mem::take(&mut existing.frame.decoders);
+                decoders.extend(candidate.frame.decoders.iter().cloned());
+
+                // Prefer the copy that needed the fewest bits fixed, and the earliest one
+                if candidate.frame.fixed_bits < existing.frame.fixed_bits {
+                    existing.frame = candidate.frame;
+                }
+                existing.end_sample = existing.end_sample.min(candidate.end_sample);
+                existing.frame.decoders = decoders;
+            }
+            None => self.pending.push(candidate),
+        }
+    }
+
+    /// Passes frames to the callback in the order they were received
+    fn emit(&mut self, mut ready: Vec<ReceivedFrame>) {
+        ready.sort_by_key(|r| r.end_sample);
+        for received in ready {
+            (self.on_frame)(received.frame);
+        }
+    }
+}
+
+/// A frame decoded by one variant, with the sample at which its closing flag arrived
+struct ReceivedFrame {
+    end_sample: usize,
+    frame: DecodedFrame,
+}
+
+/// One demodulator variant with its PLL and HDLC receiver
+struct Channel {
+    name: &'static str,
+    demodulator: Box<dyn Demodulator + Send>,
+    clock_recovery: ClockRecovery,
+    hdlc: HdlcDecoder,
+    // Where the last flag ended and the loudest sample since then, which gives the
+    // start and the level of the frame the next flag closes
+    flag_sample: usize,
+    peak: u16,
+    heard_flag: bool,
+}
+
+impl Channel {
+    fn new(sample_rate: u32, options: &DecodeOptions, variant: &DemodulatorVariant) -> Self {
+        Channel {
+            name: variant.name,
+            demodulator: new_demodulator(options.demodulator, sample_rate, variant),
+            clock_recovery: ClockRecovery::new(sample_rate, variant.sampling_phase),
+            hdlc: HdlcDecoder::new(),
+            flag_sample: 0,
+            peak: 0,
+            heard_flag: false,
+        }
+    }
+
+    /// Runs a block of samples through the demodulator, PLL and HDLC receiver
+    /// Returns the frames completed in this block
+    fn process(&mut self, block: &[i16], first_sample: usize, sample_rate: u32, options: &DecodeOptions) -> Vec<ReceivedFrame> {
+        let mut frames = Vec::new();
+
+        for (offset, &sample) in block.iter().enumerate() {
+            let i = first_sample + offset;
+            self.peak = self.peak.max(sample.unsigned_abs());
+
+            let Some(tone) = self.clock_recovery.process(self.demodulator.process(sample as f32)) else {
+                continue;
+            };
+
+            match self.hdlc.push_tone(tone) {
+                Some(HdlcEvent::Frame(frame)) => {
+                    debug!("[{}] HDLC frame of {} bytes received", self.name, frame.len());
+                    if let Ok(mut decoded_frame) = decode_frame(frame, options) {
+                        decoded_frame.sample_offset = self.flag_sample;
+                        decoded_frame.timestamp = self.flag_sample as f64 / sample_rate as f64;
+                        decoded_frame.audio_level = (self.peak as u32 * 100) / i16::MAX as u32;
+                        decoded_frame.decoders.push(self.name.to_string());
+                        frames.push(ReceivedFrame { end_sample: i, frame: decoded_frame });
+                    }
+                }
+                Some(HdlcEvent::Flag) => {}
+                None => continue,
+            }
+
+            // Any flag may be the opening flag of the next frame
+            self.heard_flag = true;
+            self.flag_sample = i;
+            self.peak = 0;
+        }
+
+        frames
+    }
+}
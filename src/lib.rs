@@ -0,0 +1,43 @@
+// Phantom Whisperer: an AX.25 / APRS codec over Bell 202 AFSK audio.
+// The library turns packets into audio samples and back, and reads and writes the
+// WAV files carrying them. It has no idea a web server or a command line exists,
+// both of those are thin consumers of what is exported here.
+
+//! Encodes a packet into audio samples and decodes it back, as the Readme shows:
+//!
+//! ```
+//! use phantom_whisperer::{decode_audio, encode_message, CodecError, DecodeOptions, EncodeOptions};
+//!
+//! fn main() -> Result<(), CodecError> {
+//!     let samples = encode_message("N0CALL", "APRS", &[], "Hello", &EncodeOptions::default())?;
+//!     for frame in decode_audio(&samples, 44100, &DecodeOptions::default())? {
+//!         println!("{}", frame.packet.to_tnc2());
+//!     }
+//!     # assert_eq!(decode_audio(&samples, 44100, &DecodeOptions::default())?[0].packet.to_tnc2(), "N0CALL>APRS:Hello");
+//!     Ok(())
+//! }
+//! ```
+
+pub mod helpers;
+pub mod models;
+
+pub use helpers::aprs::{encode_information, encode_packet, parse_information, parse_packet};
+pub use helpers::decoder::{decode_audio, decode_wav, read_wav};
+pub use helpers::encoder::{encode_frames, encode_message, encode_wav, write_wav};
+pub use helpers::errors::CodecError;
+pub use helpers::messaging::{MessageStore, RetryPolicy};
+pub use helpers::objects::{BeaconPolicy, ObjectStore};
+pub use helpers::segmentation::{reassemble, Reassembler};
+pub use helpers::stream_decoder::StreamDecoder;
+pub use models::aprs_data::{AprsData, AprsObject, CompressionOrigin, CompressionType, GpsFix, Message, MessageAck, MicE, MicEMessage, NmeaSource, Position, Timestamp};
+pub use models::aprs_packet::AprsPacket;
+pub use models::decode_options::{DecodeOptions, DemodulatorKind, FixBits};
+pub use models::decoded_frame::{CrcStatus, DecodedFrame};
+pub use models::encode_options::{ChannelLayout, EncodeOptions, SampleFormat};
+pub use models::outgoing_message::{DeliveryState, MessageRequest, OutgoingMessage};
+pub use models::reassembled_message::{ReassembledMessage, SegmentState, SegmentStatus};
+pub use models::scheduled_frame::ScheduledFrame;
+pub use models::tracked_object::{ObjectKill, ObjectRequest, TrackedObject};
+
+#[cfg(test)]
+mod tests;
@@ -1,20 +1,41 @@
 use warp::Filter;
-use std::path::Path;
-mod helpers;
+use clap::Parser;
+use phantom_whisperer::{DecodeOptions, EncodeOptions, MessageStore, ObjectKill, ObjectStore};
+use std::sync::{Arc, Mutex};
+mod cli;
 mod routes;
-mod models;
-
-#[cfg(test)]
-mod tests;
 
 use routes::encoder::create_packet;
 use routes::decoder::decode_audio;
-use helpers::errors::handle_rejection;
+use routes::errors::handle_rejection;
+use routes::messages::{list_messages, queue_message, transmit_due, SharedMessageStore};
+use routes::objects::{kill_object, list_objects, set_object, transmit_beacons, SharedObjectStore};
+use cli::{Cli, Command, ServeArgs};
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+
+    // Without a subcommand, start the server with its defaults
+    let result = match cli.command.unwrap_or(Command::Serve(ServeArgs::default())) {
+        Command::Serve(args) => {
+            serve(args).await;
+            Ok(())
+        }
+        Command::Encode(args) => cli::encode(args),
+        Command::Decode(args) => cli::decode(args),
+        Command::Inspect(args) => cli::inspect(args),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn serve(args: ServeArgs) {
     // Get the canonical path to the web directory
-    let web_dir = Path::new("web").canonicalize().expect("web directory not found");
+    let web_dir = args.web_dir.canonicalize().expect("web directory not found");
 
     // Route to serve the index.html file at the root
     let index_route = warp::get()
@@ -25,19 +46,79 @@ async fn main() {
     let static_route = warp::path("static")
         .and(warp::fs::dir(web_dir));
 
+    // Messages we send stay in the store until they're acknowledged or run out of retries
+    let message_store: SharedMessageStore = Arc::new(Mutex::new(MessageStore::default()));
+    let with_message_store = warp::any().map(move || message_store.clone());
+
+    // Objects we put on the map are beaconed again and again until they're killed
+    let object_store: SharedObjectStore = Arc::new(Mutex::new(ObjectStore::default()));
+    let with_object_store = warp::any().map(move || object_store.clone());
+
     // Route that will encode a message and return the packet encoded
     let encode_route = warp::path("encode")
         .and(warp::post())
         .and(warp::body::json())
-        .and_then(create_packet);
+        .and_then(create_packet)
+        .recover(handle_rejection);
 
     // Route that will decode a WAV file and return the message
     let decode_route = warp::path("decode")
         .and(warp::post())
+        .and(with_message_store.clone())
+        .and(warp::query::<DecodeOptions>())
         .and(warp::multipart::form().max_length(5_000_000))
         .and_then(decode_audio)
         .recover(handle_rejection);
 
+    // Routes to queue a message, list the messages with their state, and get the audio of
+    // those due for a transmission
+    let queue_message_route = warp::path!("messages")
+        .and(warp::post())
+        .and(with_message_store.clone())
+        .and(warp::body::json())
+        .and_then(queue_message)
+        .recover(handle_rejection);
+
+    let list_messages_route = warp::path!("messages")
+        .and(warp::get())
+        .and(with_message_store.clone())
+        .and_then(list_messages);
+
+    let transmit_route = warp::path!("messages" / "transmit")
+        .and(warp::post())
+        .and(with_message_store.clone())
+        .and(warp::query::<EncodeOptions>())
+        .and_then(transmit_due)
+        .recover(handle_rejection);
+
+    // Routes to create or update an object, kill one, list them, and get the audio of those
+    // due for a beacon
+    let set_object_route = warp::path!("objects")
+        .and(warp::post())
+        .and(with_object_store.clone())
+        .and(warp::body::json())
+        .and_then(set_object)
+        .recover(handle_rejection);
+
+    let kill_object_route = warp::path!("objects")
+        .and(warp::delete())
+        .and(with_object_store.clone())
+        .and(warp::query::<ObjectKill>())
+        .and_then(kill_object)
+        .recover(handle_rejection);
+
+    let list_objects_route = warp::path!("objects")
+        .and(warp::get())
+        .and(with_object_store.clone())
+        .and_then(list_objects);
+
+    let beacon_route = warp::path!("objects" / "transmit")
+        .and(warp::post())
+        .and(with_object_store.clone())
+        .and(warp::query::<EncodeOptions>())
+        .and_then(transmit_beacons)
+        .recover(handle_rejection);
+
     // Route to confirm the server is up and running
     let health_route = warp::path("health").map(|| "Server is up and running");
 
@@ -46,22 +127,29 @@ async fn main() {
         .or(static_route)
         .or(encode_route)
         .or(decode_route)
+        .or(queue_message_route)
+        .or(list_messages_route)
+        .or(transmit_route)
+        .or(set_object_route)
+        .or(kill_object_route)
+        .or(list_objects_route)
+        .or(beacon_route)
         .or(health_route);
 
     // Add CORS support, TODO: Add proper configuration for production
     let cors = warp::cors()
         .allow_any_origin()
         .allow_headers(vec!["Content-Type"])
-        .allow_methods(vec!["POST"]);
+        .allow_methods(vec!["GET", "POST", "DELETE"]);
 
     // Apply CORS to our routes
     let routes = routes.with(cors);
 
     // Notify the user that the server is running
-    println!("Starting server on http://0.0.0.0:3030");
+    println!("Starting server on http://{}:{}", args.bind, args.port);
 
-    // Start the warp server on port 3030
+    // Start the warp server, bind to 0.0.0.0 for docker
     warp::serve(routes)
-        .run(([127, 0, 0, 1], 3030)) // Should be 0.0.0.0 for docker
+        .run((args.bind, args.port))
         .await;
 }
@@ -0,0 +1,312 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// AprsData is what the information field of an APRS packet carries, once its data type
+// identifier (the first character) is known. In JSON the kind of data is in "type":
+// - position: a position report, sent with '!' or '=' without a timestamp, '/' or '@' with one
+// - mic_e: a Mic-E report, split between the destination address and the information field
+// - message: a text message to another station, with an ID when it wants an acknowledgement
+// - ack and reject: the answer of the addressee to the message with that ID
+// - object and item: something placed on the map by a station other than itself, like a
+//   checkpoint, sent with ';' for objects and ')' for items
+//
+// A Position holds:
+// - Latitude and longitude in decimal degrees, north and east positive
+// - The position ambiguity, how many trailing digits the station hid (0 to 4)
+// - The symbol table ('/', '\' or an overlay character) and the symbol code drawn on the map
+// - The timestamp, when the report has one
+// - Whether the station can take APRS messages ('=' and '@')
+// - Whether the position is in the compressed (base-91) layout, and its compression type byte
+// - Course in degrees and speed in knots, altitude in feet and radio range in miles, when sent
+// - The comment following the position, without the data it carried
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AprsData {
+    Position(Position),
+    MicE(MicE),
+    Message(Message),
+    Ack(MessageAck),
+    Reject(MessageAck),
+    Object(AprsObject),
+    Item(AprsObject),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub ambiguity: u8,
+    pub symbol_table: char,
+    pub symbol_code: char,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<Timestamp>,
+    pub messaging: bool,
+    pub compressed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression_type: Option<CompressionType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub course: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<f64>,
+    pub comment: String,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position {
+            latitude: 0.0,
+            longitude: 0.0,
+            ambiguity: 0,
+            // A house, the symbol of a fixed station
+            symbol_table: '/',
+            symbol_code: '-',
+            timestamp: None,
+            messaging: false,
+            compressed: false,
+            compression_type: None,
+            course: None,
+            speed: None,
+            altitude: None,
+            range: None,
+            comment: String::new(),
+        }
+    }
+}
+
+// APRS timestamps come in three 7 character formats, told apart by their last character:
+// - DDHHMMz: day of the month, hour and minute in UTC
+// - DDHHMM/: day of the month, hour and minute in the sender's local time
+// - HHMMSSh: hour, minute and second in UTC
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum Timestamp {
+    DayHourMinuteUtc { day: u8, hour: u8, minute: u8 },
+    DayHourMinuteLocal { day: u8, hour: u8, minute: u8 },
+    HourMinuteSecond { hour: u8, minute: u8, second: u8 },
+}
+
+impl Timestamp {
+    /// Checks every field is within its calendar or clock range
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Timestamp::DayHourMinuteUtc { day, hour, minute } | Timestamp::DayHourMinuteLocal { day, hour, minute } =>
+                (1..=31).contains(&day) && hour < 24 && minute < 60,
+            Timestamp::HourMinuteSecond { hour, minute, second } => hour < 24 && minute < 60 && second < 60,
+        }
+    }
+
+    /// Day of the month, hour and minute in UTC of a point in time
+    pub fn utc(time: SystemTime) -> Self {
+        let seconds = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let (days, seconds_of_day) = (seconds / 86400, seconds % 86400);
+
+        // Day of the month from the days since 1970-01-01, counting in 400 year eras that
+        // start on March 1st so the leap day is the last day of the year
+        let day_of_era = (days + 719468) % 146097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+
+        Timestamp::DayHourMinuteUtc {
+            day: day as u8,
+            hour: (seconds_of_day / 3600) as u8,
+            minute: (seconds_of_day / 60 % 60) as u8,
+        }
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Timestamp::DayHourMinuteUtc { day, hour, minute } => write!(f, "{:02}{:02}{:02}z", day, hour, minute),
+            Timestamp::DayHourMinuteLocal { day, hour, minute } => write!(f, "{:02}{:02}{:02}/", day, hour, minute),
+            Timestamp::HourMinuteSecond { hour, minute, second } => write!(f, "{:02}{:02}{:02}h", hour, minute, second),
+        }
+    }
+}
+
+// The compression type byte of a compressed position says where the position came from:
+// - gps_fix: whether the GPS fix is current or old
+// - nmea_source: the NMEA sentence it was read from, GGA means the cs bytes hold the altitude
+// - origin: what compressed it, a tracker, a TNC beacon text, software...
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct CompressionType {
+    pub gps_fix: GpsFix,
+    pub nmea_source: NmeaSource,
+    pub origin: CompressionOrigin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GpsFix {
+    Old,
+    #[default]
+    Current,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NmeaSource {
+    #[default]
+    Other,
+    Gll,
+    Gga,
+    Rmc,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CompressionOrigin {
+    Compressed,
+    TncBtext,
+    #[default]
+    Software,
+    Tbd,
+    Kpc3,
+    Pico,
+    OtherTracker,
+    Digipeater,
+}
+
+impl CompressionType {
+    /// Reads the 6 bits of a compression type byte, once its base-91 offset is removed
+    pub fn from_bits(bits: u8) -> Self {
+        let gps_fix = if bits & 0x20 != 0 { GpsFix::Current } else { GpsFix::Old };
+        let nmea_source = [NmeaSource::Other, NmeaSource::Gll, NmeaSource::Gga, NmeaSource::Rmc][(bits >> 3 & 0x03) as usize];
+        let origin = [
+            CompressionOrigin::Compressed,
+            CompressionOrigin::TncBtext,
+            CompressionOrigin::Software,
+            CompressionOrigin::Tbd,
+            CompressionOrigin::Kpc3,
+            CompressionOrigin::Pico,
+            CompressionOrigin::OtherTracker,
+            CompressionOrigin::Digipeater,
+        ][(bits & 0x07) as usize];
+
+        CompressionType { gps_fix, nmea_source, origin }
+    }
+
+    /// Packs the compression type into 6 bits, before the base-91 offset is added
+    pub fn to_bits(self) -> u8 {
+        (self.gps_fix as u8) << 5 | (self.nmea_source as u8) << 3 | self.origin as u8
+    }
+}
+
+// A Mic-E report holds:
+// - Latitude and longitude in decimal degrees, and the position ambiguity, as in a Position
+// - The symbol table and symbol code
+// - The message code the operator picked, one of the standard or custom ones, or emergency
+// - Speed in knots and course in degrees, 0 when unknown and 360 for north
+// - Altitude in feet, when sent
+// - The radio or tracker that sent it, when its type codes are known
+// - Whether the GPS data is current ('`') or old ('\'')
+// - The comment (status text) following all of that
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct MicE {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub ambiguity: u8,
+    pub symbol_table: char,
+    pub symbol_code: char,
+    pub message: MicEMessage,
+    pub speed: u16,
+    pub course: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    pub current: bool,
+    pub comment: String,
+}
+
+impl Default for MicE {
+    fn default() -> Self {
+        MicE {
+            latitude: 0.0,
+            longitude: 0.0,
+            ambiguity: 0,
+            // A car, Mic-E comes from mobiles more often than not
+            symbol_table: '/',
+            symbol_code: '>',
+            message: MicEMessage::OffDuty,
+            speed: 0,
+            course: 0,
+            altitude: None,
+            device: None,
+            current: true,
+            comment: String::new(),
+        }
+    }
+}
+
+// Mic-E message codes, three bits carried by the first three characters of the destination.
+// The standard ones are listed from 111 (off duty) down to 001 (priority), the custom ones
+// from 111 (custom0) down to 001 (custom6), and 000 is an emergency either way. A mix of
+// standard and custom bits means nothing, it decodes as unknown.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MicEMessage {
+    #[default]
+    OffDuty,
+    EnRoute,
+    InService,
+    Returning,
+    Committed,
+    Special,
+    Priority,
+    Custom0,
+    Custom1,
+    Custom2,
+    Custom3,
+    Custom4,
+    Custom5,
+    Custom6,
+    Emergency,
+    Unknown,
+}
+
+// A message goes to an addressee, a callsign-SSID of up to 9 characters. Its ID is 1 to 5
+// letters and digits, messages without one aren't acknowledged.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub addressee: String,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+// Acks and rejects are messages too, sent back to the station that asked for them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MessageAck {
+    pub addressee: String,
+    pub id: String,
+}
+
+// Objects and items share their fields:
+// - The name, up to 9 characters for objects and 3 to 9 for items, which can't hold '!' or '_'
+// - Whether it's live, or killed to take it off the map
+// - Its position, with the symbol it's drawn with and its comment. Objects carry a timestamp
+//   there too, items never do, and neither uses the messaging flag
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AprsObject {
+    pub name: String,
+    #[serde(default = "default_live")]
+    pub live: bool,
+    #[serde(flatten)]
+    pub position: Position,
+}
+
+fn default_live() -> bool {
+    true
+}
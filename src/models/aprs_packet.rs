@@ -1,17 +1,26 @@
 use serde::{Deserialize, Serialize};
+use super::aprs_data::AprsData;
 
 // AprsPacket struct based on the AX.25 protocol, it consists of the following fields:
 // - Source address
 // - Destination address
 // - Digipeaters: a list of digipeaters that will repeat the packet
 // - Information field
+// - The APRS data in the information field, parsed when decoding. When encoding, a packet
+//   can give this instead of the information field, which is then built from it, along with
+//   the destination for formats that put data there (Mic-E)
 
 #[derive(Deserialize, Serialize)]
 pub struct AprsPacket {
     pub source: String,
+    #[serde(default)]
     pub destination: String,
+    #[serde(default)]
     pub digipeaters: Vec<String>,
+    #[serde(default)]
     pub information: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aprs: Option<AprsData>,
 }
 
 impl AprsPacket {
@@ -19,14 +28,27 @@ impl AprsPacket {
     pub fn new(
         source: &str,
         destination: &str,
-        digipeaters: &Vec<String>,
+        digipeaters: &[String],
         information: &str,
     ) -> Self {
         AprsPacket {
             source: source.to_string(),
             destination: destination.to_string(),
-            digipeaters: digipeaters.clone(),
-            information: information.to_string()
+            digipeaters: digipeaters.to_vec(),
+            information: information.to_string(),
+            aprs: None,
         }
     }
+
+    /// Formats the packet the way TNC2 monitors print it: SOURCE>DEST,DIGI1,DIGI2:information
+    /// Returns the monitor line, without a line ending
+    pub fn to_tnc2(&self) -> String {
+        let mut path = self.destination.clone();
+        for digipeater in &self.digipeaters {
+            path.push(',');
+            path.push_str(digipeater);
+        }
+
+        format!("{}>{}:{}", self.source, path, self.information)
+    }
 }
@@ -0,0 +1,40 @@
+use serde::Deserialize;
+
+// Options accepted by the decoder, the /decode route reads them from the query string:
+// - fix_bits: how hard to try repairing a frame whose FCS does not validate
+// - demodulator: which demodulator turns the audio into tones
+// - parallel: run the whole bank of demodulator variants instead of only the default one
+// - keep_bad_fcs: also report frames whose FCS does not validate, marked as invalid
+// - reassemble: also put segmented messages back together, /decode then answers with
+//   both the frames and the messages
+// - channel: which channel of a WAV file to decode, counting from 0, all of them are mixed when missing
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "server", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum FixBits {
+    #[default]
+    None,
+    Single,
+    Double,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "server", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum DemodulatorKind {
+    Fft,
+    #[default]
+    Correlator,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct DecodeOptions {
+    pub fix_bits: FixBits,
+    pub demodulator: DemodulatorKind,
+    pub parallel: bool,
+    pub keep_bad_fcs: bool,
+    pub reassemble: bool,
+    pub channel: Option<u16>,
+}
@@ -0,0 +1,32 @@
+use serde::Serialize;
+use super::aprs_packet::AprsPacket;
+
+// DecodedFrame is what the decoder hands back for each frame it receives:
+// - The APRS packet itself, flattened so the JSON keeps the packet fields at the top level
+// - Where the frame starts in the recording, as a sample offset and in seconds
+// - The audio level of the frame, in percent of full scale
+// - Whether the FCS validated as received, after fixing bits, or not at all
+// - The number of bits flipped to make the FCS validate (0 when it arrived intact)
+// - The frame check sequence, which also identifies the frame when merging duplicates
+// - The demodulator variants that decoded it
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CrcStatus {
+    Valid,
+    Corrected,
+    Invalid,
+}
+
+#[derive(Serialize)]
+pub struct DecodedFrame {
+    #[serde(flatten)]
+    pub packet: AprsPacket,
+    pub sample_offset: usize,
+    pub timestamp: f64,
+    pub audio_level: u32,
+    pub crc: CrcStatus,
+    pub fixed_bits: u32,
+    pub fcs: u16,
+    pub decoders: Vec<String>,
+}
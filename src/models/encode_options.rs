@@ -0,0 +1,129 @@
+use serde::Deserialize;
+use crate::helpers::constants::{BAUD_RATE, DEFAULT_TXDELAY_MS, DEFAULT_TXTAIL_MS, MAX_CHANNELS, MAX_GAP_MS, MAX_LEADING_SILENCE_MS, MAX_SAMPLE_RATE, MAX_TXDELAY_MS, MAX_TXTAIL_MS, MIN_SAMPLE_RATE, SAMPLE_RATE};
+use crate::helpers::errors::CodecError;
+
+// Options accepted by the encoder, the /encode route reads them next to the packet fields:
+// - amplitude: peak level of the generated tones, from 0.0 to 1.0 of full scale
+// - shaping: glide between mark and space along a raised cosine instead of switching abruptly
+// - sample_rate: rate of the generated audio, it doesn't need to be a multiple of the baud rate
+// - sample_format: how samples are stored in the WAV file, 16 or 24 bit integers or 32 bit floats
+// - channels: number of channels in the WAV file
+// - layout: which of those channels carry the signal, the others are left silent
+// - txdelay_ms: flags sent before each frame, while the transmitter keys up and the receiver's PLL locks
+// - txtail_ms: flags sent after each frame, so the transmitter doesn't unkey on the closing flag
+// - leading_silence_ms: silence before the first frame, for VOX circuits to trigger on
+// - gap_ms: silence between transmissions when a recording holds several frames or repeats
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "server", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum SampleFormat {
+    #[default]
+    I16,
+    I24,
+    F32,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "server", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelLayout {
+    /// The first channel only
+    Left,
+    /// The second channel only
+    Right,
+    /// Every channel
+    #[default]
+    Both,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct EncodeOptions {
+    pub amplitude: f32,
+    pub shaping: bool,
+    pub sample_rate: u32,
+    pub sample_format: SampleFormat,
+    pub channels: u16,
+    pub layout: ChannelLayout,
+    pub txdelay_ms: u32,
+    pub txtail_ms: u32,
+    pub leading_silence_ms: u32,
+    pub gap_ms: u32,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            amplitude: 1.0,
+            shaping: false,
+            sample_rate: SAMPLE_RATE,
+            sample_format: SampleFormat::I16,
+            channels: 1,
+            layout: ChannelLayout::Both,
+            txdelay_ms: DEFAULT_TXDELAY_MS,
+            txtail_ms: DEFAULT_TXTAIL_MS,
+            leading_silence_ms: 0,
+            gap_ms: 0,
+        }
+    }
+}
+
+impl EncodeOptions {
+    /// Checks the options describe a WAV file that can be written, of a sensible length
+    /// Returns an error naming the first option that can't be honoured
+    pub fn validate(&self) -> Result<(), CodecError> {
+        if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&self.sample_rate) {
+            return Err(CodecError::UnsupportedWavFormat(format!("sample rate of {} Hz", self.sample_rate)));
+        }
+        if self.channels == 0 || self.channels > MAX_CHANNELS {
+            return Err(CodecError::UnsupportedWavFormat(format!("{} channels", self.channels)));
+        }
+        if self.layout == ChannelLayout::Right && self.channels < 2 {
+            return Err(CodecError::UnsupportedWavFormat("right channel layout in a mono file".to_string()));
+        }
+
+        // Every millisecond turns into flags or samples held in memory, so keep them to a few seconds
+        for (name, value, max) in [
+            ("txdelay_ms", self.txdelay_ms, MAX_TXDELAY_MS),
+            ("txtail_ms", self.txtail_ms, MAX_TXTAIL_MS),
+            ("leading_silence_ms", self.leading_silence_ms, MAX_LEADING_SILENCE_MS),
+            ("gap_ms", self.gap_ms, MAX_GAP_MS),
+        ] {
+            if value > max {
+                return Err(CodecError::InvalidOptions(format!("{} of {} is over the {} ms limit", name, value, max)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of flags filling the TXDELAY, rounded up
+    pub fn preamble_flags(&self) -> usize {
+        flags_for(self.txdelay_ms)
+    }
+
+    /// Number of flags filling the TXTAIL, rounded up
+    /// At least one, the receiver needs a few bits past the closing flag to see it
+    pub fn postamble_flags(&self) -> usize {
+        flags_for(self.txtail_ms).max(1)
+    }
+
+    /// Number of samples of silence before the first frame
+    pub fn leading_silence_samples(&self) -> usize {
+        self.samples_for(self.leading_silence_ms)
+    }
+
+    /// Number of samples of silence between two transmissions
+    pub fn gap_samples(&self) -> usize {
+        self.samples_for(self.gap_ms)
+    }
+
+    fn samples_for(&self, duration_ms: u32) -> usize {
+        (duration_ms as u64 * self.sample_rate as u64 / 1000) as usize
+    }
+}
+
+/// Converts a duration into the number of flags it takes to send at the baud rate
+fn flags_for(duration_ms: u32) -> usize {
+    (duration_ms as f64 * BAUD_RATE as f64 / 8000.0).ceil() as usize
+}
@@ -0,0 +1,33 @@
+use serde::Deserialize;
+use super::aprs_packet::AprsPacket;
+use super::encode_options::EncodeOptions;
+use super::scheduled_frame::ScheduledFrame;
+
+// EncodeRequest is the body of the /encode route, both parts are flattened so the
+// JSON stays a single object: the packet fields plus any encoder options.
+// Instead of the packet fields, a "frames" list puts several transmissions in one recording.
+
+#[derive(Deserialize)]
+pub struct EncodeRequest {
+    #[serde(flatten)]
+    pub content: EncodeContent,
+    #[serde(flatten)]
+    pub options: EncodeOptions,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum EncodeContent {
+    Frames { frames: Vec<ScheduledFrame> },
+    Packet(Box<AprsPacket>),
+}
+
+impl EncodeContent {
+    /// Turns a single packet into a one entry schedule, so both shapes encode the same way
+    pub fn into_frames(self) -> Vec<ScheduledFrame> {
+        match self {
+            EncodeContent::Frames { frames } => frames,
+            EncodeContent::Packet(packet) => vec![ScheduledFrame::new(*packet, 1)],
+        }
+    }
+}
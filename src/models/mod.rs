@@ -1 +1,10 @@
+pub mod aprs_data;
 pub mod aprs_packet;
+pub mod decode_options;
+pub mod decoded_frame;
+pub mod encode_options;
+pub mod encode_request;
+pub mod outgoing_message;
+pub mod tracked_object;
+pub mod reassembled_message;
+pub mod scheduled_frame;
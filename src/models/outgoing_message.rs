@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+// OutgoingMessage is a message the message store is sending, and what became of it:
+// - The addresses it goes out with, and the addressee and text of the message itself
+// - The ID the store gave it, the addressee acknowledges it with that
+// - Its state: still pending, delivered when the ack came in, rejected, or expired when
+//   every retry went out without an answer
+// - How many times it was transmitted so far
+// MessageRequest is the body of POST /messages, the fields a message is queued with.
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryState {
+    Pending,
+    Delivered,
+    Rejected,
+    Expired,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct OutgoingMessage {
+    pub source: String,
+    pub destination: String,
+    pub digipeaters: Vec<String>,
+    pub addressee: String,
+    pub text: String,
+    pub id: String,
+    pub state: DeliveryState,
+    pub attempts: u32,
+    #[serde(skip)]
+    pub next_attempt: Option<Instant>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct MessageRequest {
+    pub source: String,
+    #[serde(default = "default_destination")]
+    pub destination: String,
+    #[serde(default)]
+    pub digipeaters: Vec<String>,
+    pub addressee: String,
+    pub text: String,
+}
+
+fn default_destination() -> String {
+    "APRS".to_string()
+}
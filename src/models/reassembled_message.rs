@@ -0,0 +1,33 @@
+use serde::Serialize;
+use super::decoded_frame::CrcStatus;
+
+// ReassembledMessage is a message that was too long for one frame, put back together
+// from the segments the receiver heard:
+// - Who sent it and to whom, with the message ID from the segment headers
+// - The text of every segment received, in order, missing ones left out
+// - Whether every segment arrived, each from a frame whose FCS validated (as received or fixed)
+// - The status of each segment: received (with the CRC status of its frame) or missing
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentState {
+    Received,
+    Missing,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SegmentStatus {
+    pub index: usize,
+    pub status: SegmentState,
+    pub crc: Option<CrcStatus>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ReassembledMessage {
+    pub source: String,
+    pub destination: String,
+    pub message_id: String,
+    pub text: String,
+    pub complete: bool,
+    pub segments: Vec<SegmentStatus>,
+}
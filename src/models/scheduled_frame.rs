@@ -0,0 +1,25 @@
+use serde::Deserialize;
+use super::aprs_packet::AprsPacket;
+
+// ScheduledFrame is one entry of a recording with several transmissions, like a beacon
+// followed by a message and its ack:
+// - The packet, with its own addresses, flattened so the JSON keeps its fields at the top level
+// - How many times it is transmitted in a row, once by default
+
+#[derive(Deserialize)]
+pub struct ScheduledFrame {
+    #[serde(flatten)]
+    pub packet: AprsPacket,
+    #[serde(default = "default_repeat")]
+    pub repeat: u8,
+}
+
+fn default_repeat() -> u8 {
+    1
+}
+
+impl ScheduledFrame {
+    pub fn new(packet: AprsPacket, repeat: u8) -> Self {
+        ScheduledFrame { packet, repeat }
+    }
+}
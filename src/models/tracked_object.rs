@@ -0,0 +1,45 @@
+use crate::models::aprs_data::AprsObject;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+// TrackedObject is an object or item the object store beacons:
+// - The addresses it goes out with, and whether it's sent as an item instead of an object
+// - The object itself: its name, whether it's live, its position and comment
+// - How many times it was beaconed since it was created, last updated or killed
+// ObjectRequest is the body of POST /objects, the fields an object is created or updated with,
+// and ObjectKill the query of DELETE /objects, naming the one to kill.
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TrackedObject {
+    pub source: String,
+    pub destination: String,
+    pub digipeaters: Vec<String>,
+    pub item: bool,
+    #[serde(flatten)]
+    pub object: AprsObject,
+    pub beacons: u32,
+    #[serde(skip)]
+    pub next_beacon: Option<Instant>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ObjectRequest {
+    pub source: String,
+    #[serde(default = "default_destination")]
+    pub destination: String,
+    #[serde(default)]
+    pub digipeaters: Vec<String>,
+    #[serde(default)]
+    pub item: bool,
+    #[serde(flatten)]
+    pub object: AprsObject,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ObjectKill {
+    pub name: String,
+}
+
+fn default_destination() -> String {
+    "APRS".to_string()
+}
@@ -1,24 +1,83 @@
-use crate::helpers::decoder;
-use crate::helpers::errors::CustomError;
+use bytes::Buf;
+use futures::TryStreamExt;
+use phantom_whisperer::helpers::{decoder, segmentation};
+use phantom_whisperer::models::decode_options::DecodeOptions;
+use phantom_whisperer::models::decoded_frame::{CrcStatus, DecodedFrame};
+use crate::routes::errors::CustomError;
+use crate::routes::messages::SharedMessageStore;
 use warp::reject::Rejection;
 
-pub async fn decode_audio(form: warp::multipart::FormData) -> Result<impl warp::Reply, Rejection> {
+pub async fn decode_audio(store: SharedMessageStore, options: DecodeOptions, form: warp::multipart::FormData) -> Result<impl warp::Reply, Rejection> {
     println!("[DECODER] --> 1. Starting decode_audio function");
-    let samples = decoder::extract_wav_from_multipart(form)
-        .await
-        .map_err(|_| warp::reject::custom(CustomError(format!("WAV extraction error"))))?;
+    let file_bytes = extract_wav_from_multipart(form).await?;
 
-    let decoded_message = decoder::decode_audio(samples.as_slice())
-        .map_err(|e| warp::reject::custom(CustomError(format!("Decoding error: {}", e))))?;
+    let decoded_frames = decoder::decode_wav(file_bytes, &options)
+        .map_err(|e| warp::reject::custom(CustomError::from(e)))?;
 
-    println!("[DECODER] --> 14. Audio decoded successfully");
+    println!("[DECODER] --> 14. Audio decoded, {} frame(s) found", decoded_frames.len());
 
+    for decoded_frame in &decoded_frames {
+        let decoded_message = &decoded_frame.packet;
+        println!("---------------------------------------");
+        println!(" At: {:.3}s (sample {})", decoded_frame.timestamp, decoded_frame.sample_offset);
+        println!(" Source: {}", decoded_message.source);
+        println!(" Destination: {}", decoded_message.destination);
+        println!(" Digipeaters: {:?}", decoded_message.digipeaters);
+        println!(" Information: {}", decoded_message.information);
+        println!(" Audio level: {}%", decoded_frame.audio_level);
+        println!(" CRC: {:?}, fixed bits: {}", decoded_frame.crc, decoded_frame.fixed_bits);
+        println!(" Decoded by: {:?}", decoded_frame.decoders);
+    }
     println!("---------------------------------------");
-    println!(" Source: {}", decoded_message.source);
-    println!(" Destination: {}", decoded_message.destination);
-    println!(" Digipeaters: {:?}", decoded_message.digipeaters);
-    println!(" Information: {}", decoded_message.information);
-    println!("---------------------------------------");
 
-    Ok(warp::reply::json(&decoded_message))
+    handle_answers(&store, &decoded_frames);
+
+    if !options.reassemble {
+        return Ok(warp::reply::json(&decoded_frames));
+    }
+
+    let messages = segmentation::reassemble(&decoded_frames);
+    for message in &messages {
+        println!(" Message {} from {}: {} segment(s), complete: {}", message.message_id, message.source, message.segments.len(), message.complete);
+    }
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "frames": decoded_frames,
+        "messages": messages,
+    })))
+}
+
+/// Hands the acks and rejects heard in a recording to the messages we are sending
+/// Frames that failed their FCS are left out, a corrupted one could pass for the ack of a message
+pub fn handle_answers(store: &SharedMessageStore, decoded_frames: &[DecodedFrame]) {
+    let mut message_store = store.lock().expect("message store lock poisoned");
+    for decoded_frame in decoded_frames.iter().filter(|f| f.crc != CrcStatus::Invalid) {
+        if let Some(message) = message_store.handle(&decoded_frame.packet) {
+            println!(" Message {} to {} is now {:?}", message.id, message.addressee, message.state);
+        }
+    }
+}
+
+async fn extract_wav_from_multipart(mut form: warp::multipart::FormData) -> Result<Vec<u8>, Rejection> {
+    // Extract the uploaded file from the form data
+    let part = form
+        .try_next()
+        .await
+        .map_err(|e| warp::reject::custom(CustomError::BadRequest(format!("Form data error: {}", e))))?
+        .ok_or_else(|| warp::reject::custom(CustomError::BadRequest("No file uploaded".to_string())))?;
+
+    eprintln!("[DECODER] --> 2. File part extracted from form data");
+
+    let file_bytes: Vec<u8> = part
+        .stream()
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
+            acc.extend_from_slice(chunk.chunk());
+            Ok(acc)
+        })
+        .await
+        .map_err(|e| warp::reject::custom(CustomError::BadRequest(format!("File read error: {}", e))))?;
+
+    eprintln!("[DECODER] --> 3. File bytes read, size: {} bytes", file_bytes.len());
+
+    Ok(file_bytes)
 }
@@ -1,17 +1,20 @@
-use crate::helpers::encoder;
+use phantom_whisperer::helpers::encoder;
+use phantom_whisperer::models::encode_request::EncodeRequest;
+use crate::routes::errors::CustomError;
 use warp::reject::Rejection;
-use crate::models::aprs_packet::AprsPacket;
 
-pub async fn create_packet(data: AprsPacket) -> Result<impl warp::Reply, Rejection> {
+pub async fn create_packet(data: EncodeRequest) -> Result<impl warp::Reply, Rejection> {
     println!("[ENCODER] --> 1. Starting create_packet function");
 
-    let encoded_packet: Vec<i16> = encoder::encode_message(
-        &data.source,
-        &data.destination,
-        &data.digipeaters,
-        &data.information,
-    );
-    println!("[ENCODER] --> 10. Message encoded, packet length: {}", encoded_packet.len());
+    let frames = data.content.into_frames();
+    let wav_buffer = encoder::encode_wav(&frames, &data.options)
+        .map_err(|e| warp::reject::custom(CustomError::from(e)))?;
+    println!("[ENCODER] --> 10. {} frame(s) encoded, WAV file size: {} bytes", frames.len(), wav_buffer.len());
 
-    encoder::create_wav_file(encoded_packet)
+    eprintln!("[ENCODER] --> 16. Returning WAV file data.\n");
+    Ok(warp::reply::with_header(
+        wav_buffer,
+        "Content-Type",
+        "audio/wav",
+    ))
 }
@@ -0,0 +1,73 @@
+use phantom_whisperer::CodecError;
+use std::{error::Error, fmt};
+use warp::http::StatusCode;
+use warp::reject::Rejection;
+use warp::Reply;
+
+// Errors the routes reject requests with. The codec's own errors are carried as they
+// are, and each one gets its own status code and a machine-readable code in the JSON
+// body, so clients can tell a bad upload from a recording without packets.
+
+#[derive(Debug)]
+pub enum CustomError {
+    /// The request itself is unusable, like a form without a file
+    BadRequest(String),
+    /// The request names something the server doesn't have
+    NotFound(String),
+    /// The codec refused the packet or the recording
+    Codec(CodecError),
+}
+
+impl warp::reject::Reject for CustomError {}
+
+impl Error for CustomError {}
+
+impl fmt::Display for CustomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CustomError::BadRequest(reason) | CustomError::NotFound(reason) => write!(f, "{}", reason),
+            CustomError::Codec(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<CodecError> for CustomError {
+    fn from(error: CodecError) -> Self {
+        CustomError::Codec(error)
+    }
+}
+
+impl CustomError {
+    /// Maps the error to the HTTP status and the code clients can match on
+    fn status_and_code(&self) -> (StatusCode, &'static str) {
+        match self {
+            CustomError::BadRequest(_) => (StatusCode::BAD_REQUEST, "bad_request"),
+            CustomError::NotFound(_) => (StatusCode::NOT_FOUND, "not_found"),
+            CustomError::Codec(error) => match error {
+                CodecError::NoFlag => (StatusCode::UNPROCESSABLE_ENTITY, "no_flag"),
+                CodecError::TruncatedAddress => (StatusCode::UNPROCESSABLE_ENTITY, "truncated_address"),
+                CodecError::TruncatedFrame => (StatusCode::UNPROCESSABLE_ENTITY, "truncated_frame"),
+                CodecError::BadFcs { .. } => (StatusCode::UNPROCESSABLE_ENTITY, "bad_fcs"),
+                CodecError::InvalidCallsign(_) => (StatusCode::BAD_REQUEST, "invalid_callsign"),
+                CodecError::MessageTooLong { .. } => (StatusCode::PAYLOAD_TOO_LARGE, "message_too_long"),
+                CodecError::UnsupportedWavFormat(_) => (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_wav_format"),
+                CodecError::InvalidAprsData(_) => (StatusCode::BAD_REQUEST, "invalid_aprs_data"),
+                CodecError::InvalidOptions(_) => (StatusCode::BAD_REQUEST, "invalid_options"),
+            },
+        }
+    }
+}
+
+pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
+    if let Some(custom_error) = err.find::<CustomError>() {
+        let (status, code) = custom_error.status_and_code();
+        let json = warp::reply::json(&serde_json::json!({
+            "error": custom_error.to_string(),
+            "code": code,
+        }));
+        Ok(warp::reply::with_status(json, status))
+    } else {
+        // Handle other rejections (optional)
+        Err(err)
+    }
+}
@@ -0,0 +1,50 @@
+use phantom_whisperer::helpers::encoder;
+use phantom_whisperer::helpers::messaging::MessageStore;
+use phantom_whisperer::models::encode_options::EncodeOptions;
+use phantom_whisperer::models::outgoing_message::MessageRequest;
+use phantom_whisperer::models::scheduled_frame::ScheduledFrame;
+use crate::routes::errors::CustomError;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use warp::http::StatusCode;
+use warp::reject::Rejection;
+use warp::Reply;
+
+// The server has no radio of its own, so the message store only says what is due.
+// Clients queue messages with POST /messages, play whatever POST /messages/transmit hands
+// back whenever it's time to (re)transmit, and upload what they hear to /decode, where
+// acks and rejects mark the messages they answer.
+
+/// The message store, shared by every route that reads or updates it
+pub type SharedMessageStore = Arc<Mutex<MessageStore>>;
+
+pub async fn queue_message(store: SharedMessageStore, request: MessageRequest) -> Result<impl Reply, Rejection> {
+    let message = store.lock().expect("message store lock poisoned")
+        .queue(request, Instant::now())
+        .map_err(|e| warp::reject::custom(CustomError::from(e)))?;
+
+    println!("[MESSAGES] --> Queued message {} from {} to {}: {}", message.id, message.source, message.addressee, message.text);
+    Ok(warp::reply::with_status(warp::reply::json(&message), StatusCode::CREATED))
+}
+
+pub async fn list_messages(store: SharedMessageStore) -> Result<impl Reply, Rejection> {
+    let store = store.lock().expect("message store lock poisoned");
+    Ok(warp::reply::json(&store.messages()))
+}
+
+pub async fn transmit_due(store: SharedMessageStore, options: EncodeOptions) -> Result<Box<dyn Reply>, Rejection> {
+    // Taking what's due counts an attempt, so refuse options the WAV can't be written with first
+    options.validate().map_err(|e| warp::reject::custom(CustomError::from(e)))?;
+
+    let packets = store.lock().expect("message store lock poisoned").due(Instant::now());
+    if packets.is_empty() {
+        return Ok(Box::new(StatusCode::NO_CONTENT));
+    }
+
+    println!("[MESSAGES] --> {} message(s) due for transmission", packets.len());
+    let frames: Vec<ScheduledFrame> = packets.into_iter().map(|packet| ScheduledFrame::new(packet, 1)).collect();
+    let wav_buffer = encoder::encode_wav(&frames, &options)
+        .map_err(|e| warp::reject::custom(CustomError::from(e)))?;
+
+    Ok(Box::new(warp::reply::with_header(wav_buffer, "Content-Type", "audio/wav")))
+}
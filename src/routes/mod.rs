@@ -1,2 +1,8 @@
 pub mod encoder;
 pub mod decoder;
+pub mod errors;
+pub mod messages;
+pub mod objects;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,63 @@
+use phantom_whisperer::helpers::encoder;
+use phantom_whisperer::helpers::objects::ObjectStore;
+use phantom_whisperer::models::aprs_data::Timestamp;
+use phantom_whisperer::models::encode_options::EncodeOptions;
+use phantom_whisperer::models::scheduled_frame::ScheduledFrame;
+use phantom_whisperer::models::tracked_object::{ObjectKill, ObjectRequest};
+use crate::routes::errors::CustomError;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+use warp::http::StatusCode;
+use warp::reject::Rejection;
+use warp::Reply;
+
+// Objects work like messages: clients create, update and kill them through /objects, and
+// play whatever POST /objects/transmit hands back whenever beacons are due.
+
+/// The object store, shared by every route that reads or updates it
+pub type SharedObjectStore = Arc<Mutex<ObjectStore>>;
+
+pub async fn set_object(store: SharedObjectStore, mut request: ObjectRequest) -> Result<impl Reply, Rejection> {
+    // Objects always carry a timestamp, stamp them with the time they were placed if none was given
+    if !request.item && request.object.position.timestamp.is_none() {
+        request.object.position.timestamp = Some(Timestamp::utc(SystemTime::now()));
+    }
+
+    let object = store.lock().expect("object store lock poisoned")
+        .set(request, Instant::now())
+        .map_err(|e| warp::reject::custom(CustomError::from(e)))?;
+
+    println!("[OBJECTS] --> {} {} placed by {}", if object.item { "Item" } else { "Object" }, object.object.name, object.source);
+    Ok(warp::reply::json(&object))
+}
+
+pub async fn kill_object(store: SharedObjectStore, kill: ObjectKill) -> Result<impl Reply, Rejection> {
+    let object = store.lock().expect("object store lock poisoned")
+        .kill(&kill.name, Instant::now())
+        .ok_or_else(|| warp::reject::custom(CustomError::NotFound(format!("no object named {}", kill.name))))?;
+
+    println!("[OBJECTS] --> Killed {}", object.object.name);
+    Ok(warp::reply::json(&object))
+}
+
+pub async fn list_objects(store: SharedObjectStore) -> Result<impl Reply, Rejection> {
+    let store = store.lock().expect("object store lock poisoned");
+    Ok(warp::reply::json(&store.objects()))
+}
+
+pub async fn transmit_beacons(store: SharedObjectStore, options: EncodeOptions) -> Result<Box<dyn Reply>, Rejection> {
+    // Taking what's due counts a beacon and drops finished kills, so refuse bad options first
+    options.validate().map_err(|e| warp::reject::custom(CustomError::from(e)))?;
+
+    let packets = store.lock().expect("object store lock poisoned").due(Instant::now());
+    if packets.is_empty() {
+        return Ok(Box::new(StatusCode::NO_CONTENT));
+    }
+
+    println!("[OBJECTS] --> {} object(s) due for a beacon", packets.len());
+    let frames: Vec<ScheduledFrame> = packets.into_iter().map(|packet| ScheduledFrame::new(packet, 1)).collect();
+    let wav_buffer = encoder::encode_wav(&frames, &options)
+        .map_err(|e| warp::reject::custom(CustomError::from(e)))?;
+
+    Ok(Box::new(warp::reply::with_header(wav_buffer, "Content-Type", "audio/wav")))
+}
@@ -0,0 +1,101 @@
+use crate::routes::decoder::handle_answers;
+use crate::routes::messages::{queue_message, transmit_due, SharedMessageStore};
+use crate::routes::objects::{kill_object, set_object, transmit_beacons, SharedObjectStore};
+use phantom_whisperer::models::aprs_data::{AprsObject, Position};
+use phantom_whisperer::models::decoded_frame::{CrcStatus, DecodedFrame};
+use phantom_whisperer::models::encode_options::{ChannelLayout, EncodeOptions};
+use phantom_whisperer::models::outgoing_message::{DeliveryState, MessageRequest};
+use phantom_whisperer::models::tracked_object::{ObjectKill, ObjectRequest};
+use phantom_whisperer::{parse_information, AprsPacket, MessageStore, ObjectStore};
+use std::sync::{Arc, Mutex};
+use warp::http::StatusCode;
+use warp::Reply;
+
+/// Options the WAV writer refuses, each one on its own
+fn bad_options() -> Vec<EncodeOptions> {
+    vec![
+        EncodeOptions { sample_rate: 1000, ..EncodeOptions::default() },
+        EncodeOptions { channels: 0, ..EncodeOptions::default() },
+        EncodeOptions { layout: ChannelLayout::Right, ..EncodeOptions::default() },
+    ]
+}
+
+#[tokio::test]
+async fn bad_transmit_options_leave_messages_untouched() {
+    let store: SharedMessageStore = Arc::new(Mutex::new(MessageStore::default()));
+    let request = MessageRequest {
+        source: "N0CALL-9".to_string(),
+        destination: "APRS".to_string(),
+        digipeaters: Vec::new(),
+        addressee: "N1CALL".to_string(),
+        text: "Hello".to_string(),
+    };
+    assert!(queue_message(store.clone(), request).await.is_ok());
+
+    for options in bad_options() {
+        assert!(transmit_due(store.clone(), options).await.is_err());
+        assert_eq!(store.lock().unwrap().messages()[0].attempts, 0);
+    }
+
+    let reply = transmit_due(store.clone(), EncodeOptions::default()).await.unwrap();
+    assert_eq!(reply.into_response().status(), StatusCode::OK);
+    assert_eq!(store.lock().unwrap().messages()[0].attempts, 1);
+}
+
+#[tokio::test]
+async fn bad_transmit_options_leave_kill_beacons_untouched() {
+    let store: SharedObjectStore = Arc::new(Mutex::new(ObjectStore::default()));
+    let request = ObjectRequest {
+        source: "N0CALL-9".to_string(),
+        destination: "APRS".to_string(),
+        digipeaters: Vec::new(),
+        item: false,
+        object: AprsObject {
+            name: "CHK1".to_string(),
+            live: true,
+            position: Position { latitude: 49.058333, longitude: -72.029167, symbol_code: 'C', ..Position::default() },
+        },
+    };
+    assert!(set_object(store.clone(), request).await.is_ok());
+    assert!(kill_object(store.clone(), ObjectKill { name: "CHK1".to_string() }).await.is_ok());
+
+    for options in bad_options() {
+        assert!(transmit_beacons(store.clone(), options).await.is_err());
+        let store = store.lock().unwrap();
+        assert_eq!((store.objects().len(), store.objects()[0].beacons), (1, 0));
+    }
+
+    let reply = transmit_beacons(store.clone(), EncodeOptions::default()).await.unwrap();
+    assert_eq!(reply.into_response().status(), StatusCode::OK);
+    assert_eq!(store.lock().unwrap().objects()[0].beacons, 1);
+}
+
+#[tokio::test]
+async fn acks_that_failed_their_fcs_are_ignored() {
+    let store: SharedMessageStore = Arc::new(Mutex::new(MessageStore::default()));
+    let request = MessageRequest {
+        source: "N0CALL-9".to_string(),
+        destination: "APRS".to_string(),
+        digipeaters: Vec::new(),
+        addressee: "N1CALL".to_string(),
+        text: "Hello".to_string(),
+    };
+    assert!(queue_message(store.clone(), request).await.is_ok());
+
+    let ack = |crc: CrcStatus| DecodedFrame {
+        packet: AprsPacket { aprs: parse_information(":N0CALL-9 :ack1"), ..AprsPacket::new("N1CALL", "APRS", &[], ":N0CALL-9 :ack1") },
+        sample_offset: 0,
+        timestamp: 0.0,
+        audio_level: 50,
+        crc,
+        fixed_bits: 0,
+        fcs: 0,
+        decoders: Vec::new(),
+    };
+
+    handle_answers(&store, &[ack(CrcStatus::Invalid)]);
+    assert_eq!(store.lock().unwrap().messages()[0].state, DeliveryState::Pending);
+
+    handle_answers(&store, &[ack(CrcStatus::Corrected)]);
+    assert_eq!(store.lock().unwrap().messages()[0].state, DeliveryState::Delivered);
+}
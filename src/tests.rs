@@ -0,0 +1,1131 @@
+use std::f32::consts::PI;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use crate::helpers::constants::{MAX_PAYLOAD_SIZE, MAX_TRANSMISSIONS, SAMPLE_RATE};
+use crate::helpers::stream_decoder::StreamDecoder;
+use crate::helpers::errors::CodecError;
+use crate::helpers::{aprs, decoder, encoder, hdlc, resampler, segmentation};
+use crate::helpers::demodulator::{new_demodulator, VARIANTS};
+use crate::helpers::messaging::{MessageStore, RetryPolicy};
+use crate::helpers::objects::{BeaconPolicy, ObjectStore};
+use crate::models::aprs_data::{AprsData, AprsObject, CompressionOrigin, CompressionType, GpsFix, Message, MessageAck, MicE, MicEMessage, NmeaSource, Position, Timestamp};
+use crate::models::aprs_packet::AprsPacket;
+use crate::models::decode_options::{DecodeOptions, DemodulatorKind, FixBits};
+use crate::models::decoded_frame::CrcStatus;
+use crate::models::encode_options::{ChannelLayout, EncodeOptions, SampleFormat};
+use crate::models::encode_request::EncodeRequest;
+use crate::models::outgoing_message::{DeliveryState, MessageRequest};
+use crate::models::scheduled_frame::ScheduledFrame;
+use crate::models::tracked_object::ObjectRequest;
+use crate::models::reassembled_message::{SegmentState, SegmentStatus};
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+
+#[test]
+fn encoded_audio_decodes_back_to_the_same_packet() {
+    let digipeaters = vec!["WIDE1-1".to_string(), "WIDE2-2".to_string()];
+    let samples = encoder::encode_message("N0CALL-9", "APRS", &digipeaters, "Hello from the test suite", &EncodeOptions::default()).unwrap();
+
+    let decoded = decoder::decode_audio(&samples, SAMPLE_RATE, &DecodeOptions::default()).unwrap().into_iter().next().expect("frame should decode");
+    assert_eq!(decoded.packet.source, "N0CALL-9");
+    assert_eq!(decoded.packet.destination, "APRS");
+    assert_eq!(decoded.packet.digipeaters, digipeaters);
+    assert_eq!(decoded.packet.information, "Hello from the test suite");
+    assert_eq!(decoded.fixed_bits, 0);
+}
+
+#[test]
+fn digipeaters_are_read_back_after_the_source_address() {
+    let digipeaters = vec!["WIDE1-1".to_string(), "WIDE2-2".to_string()];
+    let frame = AprsPacket::new("N0CALL-9", "APRS", &digipeaters, "Via two digipeaters").encode().unwrap();
+
+    // Walk the address field as a receiver does, 7 bytes at a time up to the last-address bit
+    let mut addresses = Vec::new();
+    for address in frame.chunks(7) {
+        let callsign: String = address[..6].iter().map(|&b| (b >> 1) as char).collect();
+        addresses.push(format!("{}-{}", callsign.trim_end(), (address[6] >> 1) & 0x0F));
+        if address[6] & 0x01 == 1 {
+            break;
+        }
+    }
+    assert_eq!(addresses, ["APRS-0", "N0CALL-9", "WIDE1-1", "WIDE2-2"]);
+
+    // The control field and protocol ID follow the last digipeater
+    assert_eq!(frame[28..30], [0x03, 0xF0]);
+}
+
+#[test]
+fn hdlc_receiver_finds_frames_at_any_bit_offset() {
+    let frame = AprsPacket::new("N0CALL", "APRS", &[], "~~~~~~ stuffing ~~~~~~").encode().unwrap();
+
+    for offset in 0..8 {
+        // Idle noise before the frame shifts it off any byte boundary
+        let mut bits = vec![false; offset];
+        bits.extend(hdlc::frame_to_bits(&frame));
+
+        let mut receiver = hdlc::HdlcDecoder::new();
+        let frames: Vec<Vec<u8>> = bits.into_iter()
+            .filter_map(|b| match receiver.push_bit(b) {
+                Some(hdlc::HdlcEvent::Frame(frame)) => Some(frame),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(frames, vec![frame.clone()]);
+    }
+}
+
+#[test]
+fn fcs_covers_addresses_and_fix_bits_repairs_them() {
+    let mut frame = AprsPacket::new("N0CALL", "APRS", &[], "Position report").encode().unwrap();
+    // Corrupt the source callsign
+    frame[8] ^= 0x04;
+
+    let strict = DecodeOptions { fix_bits: FixBits::None, ..DecodeOptions::default() };
+    assert!(matches!(decoder::decode_frame(frame.clone(), &strict), Err(CodecError::BadFcs { .. })));
+
+    let single = DecodeOptions { fix_bits: FixBits::Single, ..DecodeOptions::default() };
+    let decoded = decoder::decode_frame(frame.clone(), &single).expect("single bit error should be fixed");
+    assert_eq!(decoded.packet.source, "N0CALL");
+    assert_eq!(decoded.fixed_bits, 1);
+
+    // Two adjacent bits need the double pass
+    frame[8] ^= 0x08;
+    assert!(decoder::decode_frame(frame.clone(), &single).is_err());
+    let double = DecodeOptions { fix_bits: FixBits::Double, ..DecodeOptions::default() };
+    let decoded = decoder::decode_frame(frame, &double).expect("adjacent bit errors should be fixed");
+    assert_eq!(decoded.packet.source, "N0CALL");
+    assert_eq!(decoded.fixed_bits, 2);
+}
+
+#[test]
+fn shaped_low_amplitude_audio_still_decodes() {
+    let options = EncodeOptions { amplitude: 0.3, shaping: true, ..EncodeOptions::default() };
+    let samples = encoder::encode_message("N0CALL", "APRS", &[], "Gliding tones", &options).unwrap();
+
+    // Amplitude is respected
+    let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap();
+    assert!(peak <= (0.3 * i16::MAX as f32) as u16 + 1);
+
+    let decoded = decoder::decode_audio(&samples, SAMPLE_RATE, &DecodeOptions::default()).unwrap().into_iter().next().expect("frame should decode");
+    assert_eq!(decoded.packet.information, "Gliding tones");
+}
+
+#[test]
+fn full_payload_survives_fractional_bit_timing() {
+    let information: String = (0..MAX_PAYLOAD_SIZE).map(|i| (b'!' + (i % 90) as u8) as char).collect();
+
+    for sample_rate in [44100, 48000, 22050] {
+        let options = EncodeOptions { sample_rate, ..EncodeOptions::default() };
+        let samples = encoder::encode_message("N0CALL", "APRS", &[], &information, &options).unwrap();
+
+        let decoded = decoder::decode_audio(&samples, sample_rate, &DecodeOptions::default()).unwrap()
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| panic!("{} Hz: no frame decoded", sample_rate));
+        assert_eq!(decoded.packet.information, information);
+    }
+}
+
+
+#[test]
+fn pll_locks_after_silence_offsets_and_rate_mismatch() {
+    // Sender runs 0.5% fast compared to what the receiver assumes
+    let options = EncodeOptions { sample_rate: 44321, ..EncodeOptions::default() };
+    let audio = encoder::encode_message("N0CALL", "APRS", &[], "Out of step", &options).unwrap();
+
+    for leading_silence in [0, 1, 17, 1000, 12345] {
+        let mut samples = vec![0i16; leading_silence];
+        samples.extend(&audio);
+
+        let decoded = decoder::decode_audio(&samples, SAMPLE_RATE, &DecodeOptions::default()).unwrap()
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| panic!("{} samples of silence: no frame decoded", leading_silence));
+        assert_eq!(decoded.packet.information, "Out of step");
+    }
+}
+
+/// Adds deterministic white noise to audio
+fn add_noise(samples: &[i16], amplitude: f32, seed: u32) -> Vec<i16> {
+    let mut state = seed;
+    samples.iter()
+        .map(|&s| {
+            // Sum of a few uniform values from a small LCG, roughly gaussian
+            let noise: f32 = (0..4)
+                .map(|_| {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+                })
+                .sum();
+            (s as f32 + noise * amplitude * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+        })
+        .collect()
+}
+
+#[test]
+fn demodulators_compare_on_the_same_noisy_input() {
+    let options = EncodeOptions { amplitude: 0.5, ..EncodeOptions::default() };
+    let clean = encoder::encode_message("N0CALL", "APRS", &[], "Through the static", &options).unwrap();
+    let noisy = add_noise(&clean, 0.5, 7);
+
+    for demodulator in [DemodulatorKind::Fft, DemodulatorKind::Correlator] {
+        let options = DecodeOptions { demodulator, ..DecodeOptions::default() };
+        let decoded = decoder::decode_audio(&clean, SAMPLE_RATE, &options).unwrap().into_iter().next().expect("clean audio should decode");
+        assert_eq!(decoded.packet.information, "Through the static");
+    }
+
+    let options = DecodeOptions { demodulator: DemodulatorKind::Correlator, ..DecodeOptions::default() };
+    let decoded = decoder::decode_audio(&noisy, SAMPLE_RATE, &options).unwrap().into_iter().next().expect("noisy audio should decode");
+    assert_eq!(decoded.packet.information, "Through the static");
+}
+
+#[test]
+fn parallel_variants_merge_duplicates_and_report_decoders() {
+    let options = EncodeOptions { amplitude: 0.5, ..EncodeOptions::default() };
+    let samples = encoder::encode_message("N0CALL", "APRS", &[], "Heard by many", &options).unwrap();
+
+    let options = DecodeOptions { parallel: true, ..DecodeOptions::default() };
+    let decoded = decoder::decode_audio(&samples, SAMPLE_RATE, &options).unwrap().into_iter().next().expect("frame should decode");
+    assert_eq!(decoded.packet.information, "Heard by many");
+    assert!(decoded.decoders.len() > 1);
+    assert!(decoded.decoders.contains(&"default".to_string()));
+
+    let mut unique = decoded.decoders.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), decoded.decoders.len());
+
+    // The wide and narrow variants hear something else than the default one with either demodulator
+    for kind in [DemodulatorKind::Fft, DemodulatorKind::Correlator] {
+        let outputs: Vec<Vec<f32>> = VARIANTS.iter()
+            .filter(|variant| ["default", "wide", "narrow"].contains(&variant.name))
+            .map(|variant| {
+                let mut demodulator = new_demodulator(kind, SAMPLE_RATE, variant);
+                samples.iter().map(|&s| demodulator.process(s as f32)).collect()
+            })
+            .collect();
+        assert_ne!(outputs[0], outputs[1], "{:?} wide", kind);
+        assert_ne!(outputs[0], outputs[2], "{:?} narrow", kind);
+    }
+}
+
+#[test]
+fn every_frame_in_a_recording_is_decoded_with_its_position() {
+    let options = EncodeOptions { amplitude: 0.5, ..EncodeOptions::default() };
+    let first = encoder::encode_message("N0CALL", "APRS", &[], "First", &options).unwrap();
+    let second = encoder::encode_message("N0CALL", "APRS", &[], "Second", &options).unwrap();
+
+    // Half a second of silence in front and between the two transmissions
+    let gap = vec![0i16; SAMPLE_RATE as usize / 2];
+    let mut samples = gap.clone();
+    samples.extend(&first);
+    samples.extend(&gap);
+    samples.extend(&second);
+
+    let decoded = decoder::decode_audio(&samples, SAMPLE_RATE, &DecodeOptions::default()).unwrap();
+    let information: Vec<&str> = decoded.iter().map(|f| f.packet.information.as_str()).collect();
+    assert_eq!(information, vec!["First", "Second"]);
+
+    // The first frame starts after the silence and its preamble, the second one after the first transmission
+    let txdelay = options.txdelay_ms as f64 / 1000.0;
+    assert!(decoded[0].timestamp > 0.5 + txdelay && decoded[0].timestamp < 0.6 + txdelay);
+    assert!(decoded[1].sample_offset > gap.len() * 2 + first.len() - second.len() / 2);
+    for frame in &decoded {
+        assert_eq!(frame.crc, CrcStatus::Valid);
+        assert!(frame.audio_level >= 45 && frame.audio_level <= 50);
+    }
+}
+
+#[test]
+fn txdelay_txtail_and_leading_silence_shape_the_transmission() {
+    let short = EncodeOptions { txdelay_ms: 50, txtail_ms: 10, ..EncodeOptions::default() };
+    let long = EncodeOptions { txdelay_ms: 500, txtail_ms: 100, leading_silence_ms: 250, ..EncodeOptions::default() };
+    assert_eq!((short.preamble_flags(), short.postamble_flags()), (8, 2));
+    assert_eq!((long.preamble_flags(), long.postamble_flags()), (75, 15));
+
+    let short_audio = encoder::encode_message("N0CALL", "APRS", &[], "Keyed up", &short).unwrap();
+    let long_audio = encoder::encode_message("N0CALL", "APRS", &[], "Keyed up", &long).unwrap();
+
+    // 80 more flags of 8 bits, and a quarter second of silence up front
+    let silence = SAMPLE_RATE as usize / 4;
+    let extra_flags = (80.0 * 8.0 * SAMPLE_RATE as f32 / 1200.0).round() as usize;
+    assert!(long_audio.len().abs_diff(short_audio.len() + silence + extra_flags) <= 1);
+    assert!(long_audio[..silence].iter().all(|&s| s == 0));
+    assert!(long_audio[silence..silence + 10].iter().any(|&s| s != 0));
+
+    for (audio, options) in [(&short_audio, &short), (&long_audio, &long)] {
+        let decoded = decoder::decode_audio(audio, SAMPLE_RATE, &DecodeOptions::default()).unwrap();
+        assert_eq!(decoded.len(), 1);
+        // The frame starts right after the TXDELAY flags and its own opening flag
+        let expected_start = options.leading_silence_ms as f64 / 1000.0 + (options.preamble_flags() + 1) as f64 * 8.0 / 1200.0;
+        assert!((decoded[0].timestamp - expected_start).abs() < 0.01, "frame at {}s", decoded[0].timestamp);
+    }
+
+    // Keying times are held to a few seconds, past that they'd only fill memory
+    for options in [
+        EncodeOptions { txdelay_ms: u32::MAX, ..EncodeOptions::default() },
+        EncodeOptions { txtail_ms: u32::MAX, ..EncodeOptions::default() },
+        EncodeOptions { leading_silence_ms: u32::MAX, ..EncodeOptions::default() },
+    ] {
+        assert!(matches!(options.validate(), Err(CodecError::InvalidOptions(_))), "{:?}", options);
+    }
+    assert!(EncodeOptions { txdelay_ms: 5000, txtail_ms: 5000, leading_silence_ms: 10000, ..EncodeOptions::default() }.validate().is_ok());
+
+    // Bad options are refused before any audio is made, so huge values fail right away
+    let packet = || ScheduledFrame::new(AprsPacket::new("N0CALL", "APRS", &[], "Keyed up"), 1);
+    let huge_delay = EncodeOptions { txdelay_ms: u32::MAX, ..EncodeOptions::default() };
+    assert!(matches!(encoder::encode_message("N0CALL", "APRS", &[], "Keyed up", &huge_delay), Err(CodecError::InvalidOptions(_))));
+    for sample_rate in [50_000_000, u32::MAX] {
+        let options = EncodeOptions { sample_rate, ..EncodeOptions::default() };
+        assert!(matches!(encoder::encode_frames(&[packet()], &options), Err(CodecError::UnsupportedWavFormat(_))));
+        assert!(matches!(encoder::encode_wav(&[packet()], &options), Err(CodecError::UnsupportedWavFormat(_))));
+    }
+}
+
+#[test]
+fn scenario_frames_are_encoded_with_their_addresses_gaps_and_repeats() {
+    // The request body the test team sends to /encode, a beacon, a message and its ack
+    let request: EncodeRequest = serde_json::from_value(serde_json::json!({
+        "frames": [
+            { "source": "N0CALL-9", "destination": "APRS", "digipeaters": ["WIDE1-1"], "information": "!4903.50N/07201.75W-Beacon", "repeat": 2 },
+            { "source": "N0CALL-9", "destination": "APRS", "digipeaters": [], "information": ":N1CALL   :Hello{1" },
+            { "source": "N1CALL", "destination": "APZ001", "digipeaters": [], "information": ":N0CALL-9 :ack1" }
+        ],
+        "gap_ms": 1000,
+        "amplitude": 0.5
+    })).unwrap();
+    assert_eq!(request.options.gap_ms, 1000);
+    let frames = request.content.into_frames();
+
+    let samples = encoder::encode_frames(&frames, &request.options).unwrap();
+    let decoded = decoder::decode_audio(&samples, SAMPLE_RATE, &DecodeOptions::default()).unwrap();
+    let tnc2: Vec<String> = decoded.iter().map(|f| f.packet.to_tnc2()).collect();
+    assert_eq!(tnc2, vec![
+        "N0CALL-9>APRS,WIDE1-1:!4903.50N/07201.75W-Beacon",
+        "N0CALL-9>APRS,WIDE1-1:!4903.50N/07201.75W-Beacon",
+        "N0CALL-9>APRS::N1CALL   :Hello{1",
+        "N1CALL>APZ001::N0CALL-9 :ack1",
+    ]);
+
+    // Every transmission starts at least a gap after the one before it
+    for pair in decoded.windows(2) {
+        assert!(pair[1].timestamp - pair[0].timestamp > 1.0);
+    }
+
+    // A plain packet body still encodes as a single frame
+    let request: EncodeRequest = serde_json::from_value(serde_json::json!({
+        "source": "N0CALL", "destination": "APRS", "digipeaters": [], "information": "Just one"
+    })).unwrap();
+    assert_eq!(request.content.into_frames().len(), 1);
+
+    // Gaps and repeats multiply each other, both are bounded
+    let packet = || AprsPacket::new("N0CALL", "APRS", &[], "Again");
+    let options = EncodeOptions { gap_ms: u32::MAX, ..EncodeOptions::default() };
+    assert!(matches!(options.validate(), Err(CodecError::InvalidOptions(_))));
+    let too_many: Vec<ScheduledFrame> = (0..2).map(|_| ScheduledFrame::new(packet(), 40)).collect();
+    assert!(matches!(encoder::encode_frames(&too_many, &EncodeOptions::default()), Err(CodecError::InvalidOptions(_))));
+}
+
+#[test]
+fn stream_decoder_keeps_state_across_blocks() {
+    let options = EncodeOptions { amplitude: 0.5, ..EncodeOptions::default() };
+    let mut samples = encoder::encode_message("N0CALL", "APRS", &[], "One", &options).unwrap();
+    samples.extend(encoder::encode_message("N0CALL", "APRS", &[], "Two", &options).unwrap());
+
+    // Small blocks run the variants one after the other, big ones on a thread each
+    for (block_size, parallel) in [(1, false), (100, false), (4096, false), (100, true), (20000, true)] {
+        let mut received = Vec::new();
+        let options = DecodeOptions { parallel, ..DecodeOptions::default() };
+        let mut stream_decoder = StreamDecoder::new(SAMPLE_RATE, &options, |frame| {
+            received.push(frame.packet.information)
+        });
+
+        for block in samples.chunks(block_size) {
+            stream_decoder.push_samples(block);
+        }
+        stream_decoder.flush();
+
+        assert_eq!(received, vec!["One", "Two"], "block size {}, parallel {}", block_size, parallel);
+    }
+}
+
+#[test]
+fn packets_print_in_tnc2_monitor_format() {
+    let digipeaters = vec!["WIDE1-1".to_string(), "WIDE2-1".to_string()];
+    let packet = AprsPacket::new("N0CALL-9", "APRS", &digipeaters, ">Monitoring");
+    assert_eq!(packet.to_tnc2(), "N0CALL-9>APRS,WIDE1-1,WIDE2-1:>Monitoring");
+}
+
+#[test]
+fn wav_files_written_by_encode_are_read_back_by_decode() {
+    let options = EncodeOptions { sample_rate: 22050, ..EncodeOptions::default() };
+    let samples = encoder::encode_message("N0CALL", "APRS", &[], "Saved to disk", &options).unwrap();
+    let frame = ScheduledFrame::new(AprsPacket::new("N0CALL", "APRS", &[], "Saved to disk"), 1);
+    let wav = encoder::encode_wav(&[frame], &options).unwrap();
+
+    let (read_samples, sample_rate) = decoder::read_wav(wav, None).expect("WAV should parse");
+    assert_eq!(sample_rate, 22050);
+    assert_eq!(read_samples, samples);
+
+    assert!(matches!(decoder::read_wav(b"not a wav file".to_vec(), None), Err(CodecError::UnsupportedWavFormat(_))));
+}
+
+#[test]
+fn wav_output_follows_the_requested_rate_format_and_layout() {
+    let frame = || ScheduledFrame::new(AprsPacket::new("N0CALL", "APRS", &[], "Any sound card"), 1);
+
+    // The modem follows the rate, down to telephone quality
+    for sample_rate in [8000, 48000] {
+        let options = EncodeOptions { sample_rate, ..EncodeOptions::default() };
+        let samples = encoder::encode_frames(&[frame()], &options).unwrap();
+        let decoded = decoder::decode_audio(&samples, sample_rate, &DecodeOptions::default()).unwrap();
+        assert_eq!(decoded[0].packet.information, "Any sound card", "{} Hz", sample_rate);
+    }
+
+    let cases = [
+        (SampleFormat::I16, 2, ChannelLayout::Left, 16, hound::SampleFormat::Int),
+        (SampleFormat::I24, 2, ChannelLayout::Right, 24, hound::SampleFormat::Int),
+        (SampleFormat::F32, 2, ChannelLayout::Both, 32, hound::SampleFormat::Float),
+        (SampleFormat::F32, 1, ChannelLayout::Left, 32, hound::SampleFormat::Float),
+    ];
+    for (sample_format, channels, layout, bits_per_sample, hound_format) in cases {
+        let options = EncodeOptions { sample_rate: 48000, sample_format, channels, layout, amplitude: 0.5, ..EncodeOptions::default() };
+        let signal = encoder::encode_frames_float(&[frame()], &options).unwrap();
+        let wav = encoder::encode_wav(&[frame()], &options).unwrap();
+
+        let mut reader = hound::WavReader::new(std::io::Cursor::new(wav)).unwrap();
+        let spec = reader.spec();
+        assert_eq!((spec.sample_rate, spec.channels, spec.bits_per_sample, spec.sample_format), (48000, channels, bits_per_sample, hound_format));
+
+        // Back to floats, whatever the storage
+        let full_scale = match sample_format {
+            SampleFormat::I16 => i16::MAX as f32,
+            SampleFormat::I24 => ((1 << 23) - 1) as f32,
+            SampleFormat::F32 => 1.0,
+        };
+        let samples: Vec<f32> = match hound_format {
+            hound::SampleFormat::Int => reader.samples::<i32>().map(|s| s.unwrap() as f32 / full_scale).collect(),
+            hound::SampleFormat::Float => reader.samples::<f32>().map(|s| s.unwrap()).collect(),
+        };
+        assert_eq!(samples.len(), signal.len() * channels as usize);
+
+        for (i, frame) in samples.chunks(channels as usize).enumerate() {
+            for (channel, &sample) in frame.iter().enumerate() {
+                let expected = match (layout, channel) {
+                    (ChannelLayout::Left, 0) | (ChannelLayout::Right, 1) | (ChannelLayout::Both, _) => signal[i],
+                    _ => 0.0,
+                };
+                assert!((sample - expected).abs() < 1e-3, "{:?} {:?} channel {}", sample_format, layout, channel);
+            }
+        }
+    }
+
+    let mono_right = EncodeOptions { layout: ChannelLayout::Right, ..EncodeOptions::default() };
+    assert!(matches!(encoder::encode_wav(&[frame()], &mono_right), Err(CodecError::UnsupportedWavFormat(_))));
+}
+
+#[test]
+fn any_wav_format_is_decoded_whatever_its_rate_and_channels() {
+    let frame = || ScheduledFrame::new(AprsPacket::new("N0CALL", "APRS", &[], "Any sound card"), 1);
+
+    let cases = [
+        (8000, SampleFormat::I16, 1, ChannelLayout::Left, None),
+        (22050, SampleFormat::I24, 2, ChannelLayout::Right, Some(1)),
+        (48000, SampleFormat::F32, 2, ChannelLayout::Both, None),
+        (96000, SampleFormat::I16, 2, ChannelLayout::Left, Some(0)),
+    ];
+    for (sample_rate, sample_format, channels, layout, channel) in cases {
+        let options = EncodeOptions { sample_rate, sample_format, channels, layout, ..EncodeOptions::default() };
+        let wav = encoder::encode_wav(&[frame()], &options).unwrap();
+
+        let decode_options = DecodeOptions { channel, ..DecodeOptions::default() };
+        let decoded = decoder::decode_wav(wav, &decode_options).unwrap();
+        assert_eq!(decoded.len(), 1, "{} Hz {:?} {:?}", sample_rate, sample_format, layout);
+        assert_eq!(decoded[0].packet.information, "Any sound card");
+
+        // Offsets are counted in samples of the file, not of the decoder
+        let seconds = decoded[0].sample_offset as f64 / sample_rate as f64;
+        assert!((seconds - decoded[0].timestamp).abs() < 0.01, "{} Hz: {}s against {}s", sample_rate, seconds, decoded[0].timestamp);
+    }
+
+    // The silent channel of a stereo file holds nothing, mixed in it only halves the level
+    let options = EncodeOptions { channels: 2, layout: ChannelLayout::Left, ..EncodeOptions::default() };
+    let wav = encoder::encode_wav(&[frame()], &options).unwrap();
+    let right = DecodeOptions { channel: Some(1), ..DecodeOptions::default() };
+    assert_eq!(decoder::decode_wav(wav.clone(), &right).err(), Some(CodecError::NoFlag));
+    let (mixed, _) = decoder::read_wav(wav.clone(), None).unwrap();
+    let (left, _) = decoder::read_wav(wav.clone(), Some(0)).unwrap();
+    assert!(mixed.iter().zip(&left).all(|(&m, &l)| (m as i32 - l as i32 / 2).abs() <= 1));
+
+    let missing = DecodeOptions { channel: Some(2), ..DecodeOptions::default() };
+    assert!(matches!(decoder::decode_wav(wav, &missing), Err(CodecError::UnsupportedWavFormat(_))));
+}
+
+#[test]
+fn resampling_keeps_the_length_and_the_tones() {
+    let tone: Vec<i16> = (0..48000).map(|i| ((i as f32 * 2.0 * PI * 1200.0 / 48000.0).sin() * 10000.0) as i16).collect();
+
+    for to in [8000, 44100, 96000] {
+        let resampled = resampler::resample(&tone, 48000, to);
+        assert_eq!(resampled.len(), to as usize);
+
+        // Still a 1200 Hz sine at the same level, checked against the ideal one away from the edges
+        let error = resampled.iter().enumerate().skip(100).take(to as usize - 200)
+            .map(|(i, &s)| (s as f32 - (i as f32 * 2.0 * PI * 1200.0 / to as f32).sin() * 10000.0).abs())
+            .fold(0.0, f32::max);
+        assert!(error < 500.0, "{} Hz: off by {}", to, error);
+    }
+
+    assert_eq!(resampler::resample(&tone, 48000, 48000), tone);
+}
+
+#[test]
+fn codec_failures_come_back_as_distinct_errors() {
+    let silence = vec![0i16; SAMPLE_RATE as usize];
+    assert_eq!(decoder::decode_audio(&silence, SAMPLE_RATE, &DecodeOptions::default()).err(), Some(CodecError::NoFlag));
+
+    let options = EncodeOptions::default();
+    assert_eq!(encoder::encode_message("N0CALL-16", "APRS", &[], "SSID too big", &options).err(), Some(CodecError::InvalidCallsign("N0CALL-16".to_string())));
+    assert_eq!(encoder::encode_message("TOOLONGCALL", "APRS", &[], "Callsign too long", &options).err(), Some(CodecError::InvalidCallsign("TOOLONGCALL".to_string())));
+
+    // A source address that claims more addresses follow, in a frame that ends right after it
+    let mut frame = AprsPacket::new("N0CALL", "APRS", &["WIDE1-1".to_string()], "").encode().unwrap();
+    frame.truncate(14 + 2);
+    let crc = crc::Crc::<u16>::new(&crc::CRC_16_IBM_SDLC).checksum(&frame);
+    frame.extend(crc.to_le_bytes());
+    assert_eq!(decoder::decode_frame(frame, &DecodeOptions::default()).err(), Some(CodecError::TruncatedAddress));
+}
+
+#[test]
+fn long_messages_are_split_into_segments_and_reassembled() {
+    // Multi-byte characters make sure segments are never cut in the middle of one
+    let text: String = (0..700).map(|i| if i % 50 == 0 { 'é' } else { (b'a' + (i % 26) as u8) as char }).collect();
+
+    let packets = encoder::prepare_packets("N0CALL", "APRS", &[], &text).unwrap();
+    assert_eq!(packets.len(), 3);
+    for packet in &packets {
+        let decoded = decoder::decode_frame(packet.clone(), &DecodeOptions::default()).unwrap();
+        assert!(decoded.packet.information.len() <= MAX_PAYLOAD_SIZE);
+    }
+
+    let samples = encoder::encode_message("N0CALL", "APRS", &[], &text, &EncodeOptions::default()).unwrap();
+    let frames = decoder::decode_audio(&samples, SAMPLE_RATE, &DecodeOptions::default()).unwrap();
+    assert_eq!(frames.len(), 3);
+
+    let messages = segmentation::reassemble(&frames);
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].complete);
+    assert_eq!(messages[0].text, text);
+    assert!(messages[0].segments.iter().all(|s| s.status == SegmentState::Received && s.crc == Some(CrcStatus::Valid)));
+
+    // Without the middle segment the message comes back incomplete, saying which one is missing
+    let without_middle: Vec<_> = [&packets[0], &packets[2]].iter()
+        .map(|packet| decoder::decode_frame(packet.to_vec(), &DecodeOptions::default()).unwrap())
+        .collect();
+    let messages = segmentation::reassemble(&without_middle);
+    assert!(!messages[0].complete);
+    assert_eq!(messages[0].segments[1], SegmentStatus { index: 2, status: SegmentState::Missing, crc: None });
+
+    // A corrupted copy heard first gives way to the good one a repeat brings
+    let decode = |i: usize| decoder::decode_frame(packets[i].clone(), &DecodeOptions::default()).unwrap();
+    let mut corrupted = decode(0);
+    corrupted.packet.information.pop();
+    corrupted.packet.information.push('#');
+    corrupted.crc = CrcStatus::Invalid;
+    let messages = segmentation::reassemble(&[corrupted, decode(1), decode(2), decode(0)]);
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].complete);
+    assert_eq!(messages[0].text, text);
+    assert_eq!(messages[0].segments[0].crc, Some(CrcStatus::Valid));
+
+    // Without that repeat, the message isn't complete
+    let mut corrupted = decode(0);
+    corrupted.crc = CrcStatus::Invalid;
+    let messages = segmentation::reassemble(&[corrupted, decode(1), decode(2)]);
+    assert_eq!(messages.len(), 1);
+    assert!(!messages[0].complete);
+    assert_eq!(messages[0].segments[0], SegmentStatus { index: 1, status: SegmentState::Received, crc: Some(CrcStatus::Invalid) });
+}
+
+/// Builds a WAV file around arbitrary bytes, with header fields that may be nonsense
+fn wav_with_header(format: u16, channels: u16, sample_rate: u32, bits_per_sample: u16, data: &[u8]) -> Vec<u8> {
+    let block_align = channels.wrapping_mul(bits_per_sample / 8);
+    let mut wav = Vec::new();
+    wav.extend(b"RIFF");
+    wav.extend((36 + data.len() as u32).to_le_bytes());
+    wav.extend(b"WAVEfmt ");
+    wav.extend(16u32.to_le_bytes());
+    wav.extend(format.to_le_bytes());
+    wav.extend(channels.to_le_bytes());
+    wav.extend(sample_rate.to_le_bytes());
+    wav.extend(sample_rate.wrapping_mul(block_align as u32).to_le_bytes());
+    wav.extend(block_align.to_le_bytes());
+    wav.extend(bits_per_sample.to_le_bytes());
+    wav.extend(b"data");
+    wav.extend((data.len() as u32).to_le_bytes());
+    wav.extend(data);
+    wav
+}
+
+#[test]
+fn uncompressed_position_reports_are_parsed() {
+    let position = |information: &str| match aprs::parse_information(information) {
+        Some(AprsData::Position(position)) => position,
+        other => panic!("{:?} parsed as {:?}", information, other),
+    };
+
+    let report = position("!4903.50N/07201.75W-Test 001234");
+    assert_eq!(report, Position {
+        latitude: 49.0 + 3.5 / 60.0,
+        longitude: -(72.0 + 1.75 / 60.0),
+        ambiguity: 0,
+        symbol_table: '/',
+        symbol_code: '-',
+        timestamp: None,
+        messaging: false,
+        comment: "Test 001234".to_string(),
+        ..Position::default()
+    });
+
+    let report = position("=3345.12S\\15112.34E>");
+    assert!(report.messaging && report.comment.is_empty());
+    assert_eq!((report.symbol_table, report.symbol_code), ('\\', '>'));
+    assert!(report.latitude < 0.0 && report.longitude > 0.0);
+
+    let report = position("@092345z4903.50N/07201.75W>Mobile");
+    assert_eq!(report.timestamp, Some(Timestamp::DayHourMinuteUtc { day: 9, hour: 23, minute: 45 }));
+    assert!(report.messaging);
+    assert_eq!(position("/092345/4903.50N/07201.75W>").timestamp, Some(Timestamp::DayHourMinuteLocal { day: 9, hour: 23, minute: 45 }));
+    assert_eq!(position("/234517h4903.50N/07201.75W>").timestamp, Some(Timestamp::HourMinuteSecond { hour: 23, minute: 45, second: 17 }));
+
+    // Hidden digits count as 0, on both coordinates
+    let report = position("!4903.  N/07201.  W-");
+    assert_eq!(report.ambiguity, 2);
+    assert_eq!(report.latitude, 49.05);
+    assert_eq!(position("!49  .  N/072  .  W-").ambiguity, 4);
+
+    // Broken fields and types we don't parse are left alone
+    for information in ["", "!", "!4903.50X/07201.75W-", "!4960.00N/07201.75W-", "!9100.00N/07201.75W-",
+        "!4903.50N/18100.00W-", "!4903.50N|07201.75W-", "!4 03.50N/07201.75W-", "@999999z4903.50N/07201.75W>",
+        "/092345x4903.50N/07201.75W>", ">Status text"] {
+        assert_eq!(aprs::parse_information(information), None, "{:?}", information);
+    }
+
+    // The decoder hands the parsed data back with the frame
+    let samples = encoder::encode_message("N0CALL", "APRS", &[], "!4903.50N/07201.75W-", &EncodeOptions::default()).unwrap();
+    let decoded = decoder::decode_audio(&samples, SAMPLE_RATE, &DecodeOptions::default()).unwrap();
+    let json = serde_json::to_value(&decoded[0]).unwrap();
+    assert_eq!(json["aprs"]["type"], "position");
+    assert_eq!(json["aprs"]["symbol_code"], "-");
+}
+
+#[test]
+fn compressed_positions_are_encoded_and_parsed_back() {
+    let position = |information: &str| match aprs::parse_information(information) {
+        Some(AprsData::Position(position)) => position,
+        other => panic!("{:?} parsed as {:?}", information, other),
+    };
+    let close = |a: f64, b: f64, tolerance: f64| (a - b).abs() < tolerance;
+
+    // The examples from the APRS specification: course and speed, altitude, then radio range
+    let report = position("!/5L!!<*e7>7P[Comment");
+    assert!(report.compressed);
+    assert!(close(report.latitude, 49.5, 1e-5) && close(report.longitude, -72.75, 1e-5));
+    assert_eq!((report.symbol_table, report.symbol_code, report.course), ('/', '>', Some(88)));
+    assert!(close(report.speed.unwrap(), 36.2, 0.05));
+    assert_eq!(report.compression_type, Some(CompressionType { gps_fix: GpsFix::Current, nmea_source: NmeaSource::Rmc, origin: CompressionOrigin::Software }));
+    assert_eq!(report.comment, "Comment");
+
+    let report = position("=/5L!!<*e7OS]S");
+    assert!(report.messaging && report.course.is_none());
+    assert!(close(report.altitude.unwrap(), 10004.0, 1.0));
+
+    let report = position("!/5L!!<*e7>{?!");
+    assert!(close(report.range.unwrap(), 20.1, 0.05));
+
+    // Nothing in the cs bytes
+    let report = position("@092345z/5L!!<*e7>  !");
+    assert_eq!((report.course, report.altitude, report.range, report.compression_type), (None, None, None, None));
+    assert!(report.timestamp.is_some());
+
+    // Numbers in, numbers out, within the resolution of the format
+    let beacon = Position {
+        latitude: -33.8688,
+        longitude: 151.2093,
+        symbol_table: '3',
+        symbol_code: '#',
+        compressed: true,
+        course: Some(180),
+        speed: Some(55.0),
+        altitude: Some(1234.0),
+        comment: "Digi".to_string(),
+        ..Position::default()
+    };
+    let information = aprs::encode_information(&AprsData::Position(beacon.clone())).unwrap();
+    assert_eq!(&information[..2], "!d", "overlay digits go out as letters");
+    let report = position(&information);
+    assert!(close(report.latitude, beacon.latitude, 1e-5) && close(report.longitude, beacon.longitude, 1e-5));
+    assert_eq!((report.symbol_table, report.course), ('3', Some(180)));
+    assert!(close(report.speed.unwrap(), 55.0, 55.0 * 0.04));
+    assert_eq!((report.altitude, report.comment.as_str()), (Some(1234.0), "Digi"), "altitude that doesn't fit the cs bytes goes in the comment");
+
+    let mountain = Position { altitude: Some(14505.0), compressed: true, ..Position::default() };
+    let report = position(&aprs::encode_information(&AprsData::Position(mountain)).unwrap());
+    assert!(close(report.altitude.unwrap(), 14505.0, 14505.0 * 0.002));
+    assert_eq!(report.compression_type.unwrap().nmea_source, NmeaSource::Gga);
+
+    // The cs bytes can't go below 1 foot, lower altitudes keep their value in the comment
+    let valley = Position { altitude: Some(-123.0), compressed: true, ..Position::default() };
+    let information = aprs::encode_information(&AprsData::Position(valley)).unwrap();
+    assert!(information.ends_with("/A=-00123"), "{}", information);
+    let report = position(&information);
+    assert_eq!((report.altitude, report.compression_type), (Some(-123.0), None));
+
+    let repeater = Position { range: Some(50.0), compressed: true, ..Position::default() };
+    let report = position(&aprs::encode_information(&AprsData::Position(repeater)).unwrap());
+    assert!(close(report.range.unwrap(), 50.0, 50.0 * 0.04));
+
+    // The same beacon uncompressed takes more room, and keeps its data extensions exactly
+    let uncompressed = Position { compressed: false, ..beacon.clone() };
+    let plain = aprs::encode_information(&AprsData::Position(uncompressed)).unwrap();
+    assert_eq!(plain, "!3352.13S315112.56E#180/055/A=001234Digi");
+    assert!(information.len() < plain.len());
+    let report = position(&plain);
+    assert_eq!((report.course, report.speed, report.altitude, report.comment.as_str()), (Some(180), Some(55.0), Some(1234.0), "Digi"));
+
+    let hidden = Position { latitude: 49.0583, longitude: -72.0292, ambiguity: 2, range: Some(25.0), ..Position::default() };
+    assert_eq!(aprs::encode_information(&AprsData::Position(hidden)).unwrap(), "!4903.  N/07201.  W-RNG0025");
+
+    // The data extensions hold whatever rounds to 3 and 4 digits, and nothing more
+    let fastest = Position { course: Some(90), speed: Some(999.4), ..Position::default() };
+    assert!(aprs::encode_information(&AprsData::Position(fastest)).unwrap().ends_with("090/999"));
+    let farthest = Position { range: Some(9999.4), ..Position::default() };
+    assert!(aprs::encode_information(&AprsData::Position(farthest)).unwrap().ends_with("RNG9999"));
+
+    // Numbers the formats can't carry are refused
+    for position in [
+        Position { course: Some(90), speed: Some(999.5), ..Position::default() },
+        Position { range: Some(9999.5), ..Position::default() },
+        Position { latitude: 91.0, ..Position::default() },
+        Position { symbol_table: 'x', ..Position::default() },
+        Position { course: Some(90), ..Position::default() },
+        Position { course: Some(90), speed: Some(10.0), range: Some(5.0), ..Position::default() },
+        Position { compressed: true, ambiguity: 1, ..Position::default() },
+        Position { compressed: true, course: Some(0), speed: Some(0.0), compression_type: Some(CompressionType { nmea_source: NmeaSource::Gga, ..CompressionType::default() }), ..Position::default() },
+    ] {
+        assert!(matches!(aprs::encode_information(&AprsData::Position(position)), Err(CodecError::InvalidAprsData(_))));
+    }
+
+    // /encode takes the APRS data in place of the information field
+    let request: EncodeRequest = serde_json::from_value(serde_json::json!({
+        "source": "N0CALL-9", "destination": "APRS", "digipeaters": [],
+        "aprs": { "type": "position", "latitude": 49.5, "longitude": -72.75, "symbol_code": ">", "compressed": true, "course": 88, "speed": 36.2 }
+    })).unwrap();
+    let samples = encoder::encode_frames(&request.content.into_frames(), &request.options).unwrap();
+    let decoded = decoder::decode_audio(&samples, SAMPLE_RATE, &DecodeOptions::default()).unwrap();
+    assert_eq!(decoded[0].packet.information, "!/5L!!<*e7>7PC");
+}
+
+#[test]
+fn mic_e_reports_are_encoded_and_parsed_back() {
+    let mic_e = |destination: &str, information: &str| match aprs::parse_packet(destination, information) {
+        Some(AprsData::MicE(report)) => report,
+        other => panic!("{} {:?} parsed as {:?}", destination, information, other),
+    };
+
+    // The example from the APRS specification, from a Kenwood TM-D710 at 61 meters
+    let report = mic_e("S32UVT-2", "`(_fn\"Oj/]\"4T}Hello=");
+    assert!((report.latitude - (33.0 + 25.64 / 60.0)).abs() < 1e-9);
+    assert!((report.longitude + (112.0 + 7.74 / 60.0)).abs() < 1e-9);
+    assert_eq!((report.symbol_table, report.symbol_code), ('/', 'j'));
+    assert_eq!((report.message, report.speed, report.course), (MicEMessage::Returning, 20, 251));
+    assert_eq!((report.altitude, report.device.as_deref()), (Some(200.0), Some("Kenwood TM-D710")));
+    assert_eq!((report.comment.as_str(), report.current), ("Hello", true));
+
+    // Numbers in, the same two fields out
+    let sent = MicE {
+        latitude: 33.0 + 25.64 / 60.0,
+        longitude: -(112.0 + 7.74 / 60.0),
+        symbol_code: 'j',
+        message: MicEMessage::Returning,
+        speed: 20,
+        course: 251,
+        altitude: Some(200.0),
+        device: Some("Kenwood TM-D710".to_string()),
+        comment: "Hello".to_string(),
+        ..MicE::default()
+    };
+    let (destination, information) = aprs::encode_packet(&AprsData::MicE(sent.clone()), "APRS-2").unwrap();
+    assert_eq!((destination.as_str(), information.as_str()), ("S32UVT-2", "`(_fn\"Oj/]\"4T}Hello="));
+    assert_eq!(mic_e(&destination, &information), sent);
+
+    // Every corner of the longitude, speed and course encoding, and every message code
+    let messages = [MicEMessage::OffDuty, MicEMessage::Priority, MicEMessage::Custom0, MicEMessage::Custom6, MicEMessage::Emergency];
+    for (i, (longitude, speed, course)) in [(5.5, 0, 0), (-45.25, 199, 360), (105.0, 200, 99), (-179.99, 799, 300), (0.0, 5, 1)].into_iter().enumerate() {
+        let sent = MicE { latitude: -12.5, longitude, speed, course, message: messages[i], current: i % 2 == 0, ..MicE::default() };
+        let (destination, information) = aprs::encode_packet(&AprsData::MicE(sent.clone()), "APRS").unwrap();
+        let report = mic_e(&destination, &information);
+        assert!((report.longitude - longitude).abs() < 1e-9, "{} came back as {}", longitude, report.longitude);
+        assert_eq!((report.latitude, report.speed, report.course, report.message, report.current), (-12.5, speed, course, messages[i], i % 2 == 0));
+    }
+
+    // Ambiguity hides the same digits of both coordinates
+    let hidden = MicE { latitude: 49.0583, longitude: -72.0292, ambiguity: 3, ..MicE::default() };
+    let (destination, information) = aprs::encode_packet(&AprsData::MicE(hidden), "APRS").unwrap();
+    assert_eq!(destination, "TYPZLZ");
+    let report = mic_e(&destination, &information);
+    assert_eq!((report.ambiguity, report.latitude, report.longitude), (3, 49.0, -72.0));
+
+    // Standard and custom bits together don't mean anything, broken fields don't parse
+    assert_eq!(mic_e("SB2UVT", "`(_fn\"Oj/").message, MicEMessage::Unknown);
+    for (destination, information) in [("APRS", "`(_fn\"Oj/"), ("S32UVTX", "`(_fn\"Oj/"), ("S32UAT", "`(_fn\"Oj/"), ("S32UVT", "`(_fn\"O"), ("S32UVT", "`(_fn\"Oj|")] {
+        assert_eq!(aprs::parse_packet(destination, information), None, "{} {:?}", destination, information);
+    }
+    for report in [MicE { speed: 800, ..MicE::default() }, MicE { message: MicEMessage::Unknown, ..MicE::default() }, MicE { device: Some("Walkie-talkie".to_string()), ..MicE::default() }] {
+        assert!(matches!(aprs::encode_packet(&AprsData::MicE(report), "APRS"), Err(CodecError::InvalidAprsData(_))));
+    }
+
+    // /encode fills in the destination, the decoder reads it back
+    let request: EncodeRequest = serde_json::from_value(serde_json::json!({
+        "source": "N0CALL-9",
+        "aprs": { "type": "mic_e", "latitude": 33.42733, "longitude": -112.129, "message": "en_route", "speed": 55, "course": 90 }
+    })).unwrap();
+    let samples = encoder::encode_frames(&request.content.into_frames(), &request.options).unwrap();
+    let decoded = decoder::decode_audio(&samples, SAMPLE_RATE, &DecodeOptions::default()).unwrap();
+    let Some(AprsData::MicE(report)) = &decoded[0].packet.aprs else { panic!("{:?}", decoded[0].packet.aprs) };
+    assert_eq!((report.message, report.speed, report.course), (MicEMessage::EnRoute, 55, 90));
+}
+
+#[test]
+fn messages_acks_and_rejects_are_encoded_and_parsed() {
+    let message = Message { addressee: "N1CALL".to_string(), text: "Meet at the checkpoint".to_string(), id: Some("42".to_string()) };
+    let information = aprs::encode_information(&AprsData::Message(message.clone())).unwrap();
+    assert_eq!(information, ":N1CALL   :Meet at the checkpoint{42");
+    assert_eq!(aprs::parse_information(&information), Some(AprsData::Message(message)));
+
+    let ack = MessageAck { addressee: "N0CALL-9".to_string(), id: "42".to_string() };
+    assert_eq!(aprs::encode_information(&AprsData::Ack(ack.clone())).unwrap(), ":N0CALL-9 :ack42");
+    assert_eq!(aprs::encode_information(&AprsData::Reject(ack.clone())).unwrap(), ":N0CALL-9 :rej42");
+    assert_eq!(aprs::parse_information(":N0CALL-9 :ack42"), Some(AprsData::Ack(ack.clone())));
+    assert_eq!(aprs::parse_information(":N0CALL-9 :rej42"), Some(AprsData::Reject(ack.clone())));
+    assert_eq!(aprs::parse_information(":N0CALL-9 :ack42}AB"), Some(AprsData::Ack(ack)), "reply-acks carry the ID first");
+
+    // No ID, no ack asked for, and a brace that isn't followed by an ID is part of the text
+    let parsed = aprs::parse_information(":BLN1     :Net tonight at 8{not an id").unwrap();
+    assert_eq!(parsed, AprsData::Message(Message { addressee: "BLN1".to_string(), text: "Net tonight at 8{not an id".to_string(), id: None }));
+    assert_eq!(aprs::parse_information(":N1CALL:unpadded"), None);
+
+    for message in [
+        Message { addressee: "TOOLONGCALL".to_string(), text: "Hi".to_string(), id: None },
+        Message { addressee: "N1CALL".to_string(), text: "x".repeat(68), id: None },
+        Message { addressee: "N1CALL".to_string(), text: "Pipes | aren't allowed".to_string(), id: None },
+        Message { addressee: "N1CALL".to_string(), text: "Hi".to_string(), id: Some("123456".to_string()) },
+    ] {
+        assert!(matches!(aprs::encode_information(&AprsData::Message(message)), Err(CodecError::InvalidAprsData(_))));
+    }
+}
+
+#[test]
+fn message_store_retries_with_backoff_until_the_ack_arrives() {
+    let policy = RetryPolicy { first_interval: Duration::from_secs(30), factor: 2, max_interval: Duration::from_secs(100), max_attempts: 4 };
+    let mut store = MessageStore::new(policy);
+    let start = Instant::now();
+    let at = |seconds: u64| start + Duration::from_secs(seconds);
+    let request = |text: &str| MessageRequest {
+        source: "N0CALL-9".to_string(),
+        destination: "APRS".to_string(),
+        digipeaters: vec!["WIDE1-1".to_string()],
+        addressee: "N1CALL".to_string(),
+        text: text.to_string(),
+    };
+
+    let first = store.queue(request("First"), start).unwrap();
+    let second = store.queue(request("Second"), start).unwrap();
+    assert_eq!((first.id.as_str(), second.id.as_str()), ("1", "2"));
+    assert!(matches!(store.queue(MessageRequest { source: "NOT A CALL".to_string(), ..request("Third") }, start), Err(CodecError::InvalidCallsign(_))));
+
+    // Both go out right away, then 30 s, 60 s and 100 s (capped) apart
+    let packets = store.due(at(0));
+    assert_eq!(packets.len(), 2);
+    let samples = encoder::encode_frames(&packets.into_iter().map(|p| ScheduledFrame::new(p, 1)).collect::<Vec<_>>(), &EncodeOptions::default()).unwrap();
+    let decoded = decoder::decode_audio(&samples, SAMPLE_RATE, &DecodeOptions::default()).unwrap();
+    assert_eq!(decoded[0].packet.to_tnc2(), "N0CALL-9>APRS,WIDE1-1::N1CALL   :First{1");
+
+    assert!(store.due(at(29)).is_empty());
+    assert_eq!(store.next_due(), Some(at(30)));
+    assert_eq!(store.due(at(30)).len(), 2);
+    assert!(store.due(at(89)).is_empty());
+    assert_eq!(store.due(at(90)).len(), 2);
+
+    // The addressee acks the first one, over the air
+    let ack = AprsPacket::new("N1CALL", "APRS", &[], ":N0CALL-9 :ack1");
+    let samples = encoder::encode_frames(&[ScheduledFrame::new(ack, 1)], &EncodeOptions::default()).unwrap();
+    let decoded = decoder::decode_audio(&samples, SAMPLE_RATE, &DecodeOptions::default()).unwrap();
+    let answered = store.handle(&decoded[0].packet).expect("the ack answers the first message");
+    assert_eq!((answered.id.as_str(), answered.state, answered.attempts), ("1", DeliveryState::Delivered, 3));
+
+    // An ack from someone else, or for a message already answered, changes nothing
+    assert!(store.handle(&AprsPacket { aprs: aprs::parse_information(":N0CALL-9 :ack2"), ..AprsPacket::new("N2CALL", "APRS", &[], ":N0CALL-9 :ack2") }).is_none());
+    assert!(store.handle(&decoded[0].packet).is_none());
+
+    // The second one keeps going until it runs out of retries
+    let retries: Vec<String> = store.due(at(190)).iter().map(|p| p.source.clone()).collect();
+    assert_eq!(retries, vec!["N0CALL-9"]);
+    assert!(store.due(at(289)).is_empty());
+    assert!(store.due(at(290)).is_empty());
+    assert_eq!(store.messages()[1].state, DeliveryState::Expired);
+    assert_eq!(store.next_due(), None);
+
+    // A reject ends a message too
+    let third = store.queue(request("Third"), at(300)).unwrap();
+    store.due(at(300));
+    let reject = AprsPacket { aprs: aprs::parse_information(&format!(":N0CALL-9 :rej{}", third.id)), ..AprsPacket::new("N1CALL", "APRS", &[], "") };
+    assert_eq!(store.handle(&reject).map(|m| m.state), Some(DeliveryState::Rejected));
+
+    // No more go out at once than a recording holds, the rest wait for the next call
+    let mut store = MessageStore::default();
+    for _ in 0..70 {
+        store.queue(request("Busy"), start).unwrap();
+    }
+    assert_eq!(store.due(start).len(), MAX_TRANSMISSIONS);
+    assert_eq!(store.due(start).len(), 70 - MAX_TRANSMISSIONS);
+
+    // Finished messages don't pile up, only the latest hundred stay listed
+    let mut store = MessageStore::new(RetryPolicy { max_attempts: 0, ..RetryPolicy::default() });
+    for i in 0..150 {
+        store.queue(request(&format!("Number {}", i)), start).unwrap();
+        store.due(start);
+    }
+    assert_eq!(store.messages().len(), 100);
+    assert!(store.messages().iter().all(|m| m.state == DeliveryState::Expired));
+    assert_eq!(store.messages()[0].text, "Number 50");
+}
+
+#[test]
+fn objects_and_items_are_encoded_and_parsed() {
+    let leader = AprsObject {
+        name: "LEADER".to_string(),
+        live: true,
+        position: Position {
+            latitude: 49.058333,
+            longitude: -72.029167,
+            symbol_code: '>',
+            timestamp: Some(Timestamp::DayHourMinuteUtc { day: 9, hour: 23, minute: 45 }),
+            course: Some(88),
+            speed: Some(36.0),
+            ..Position::default()
+        },
+    };
+    let information = aprs::encode_information(&AprsData::Object(leader.clone())).unwrap();
+    assert_eq!(information, ";LEADER   *092345z4903.50N/07201.75W>088/036");
+    let parsed = aprs::parse_information(&information).unwrap();
+    let AprsData::Object(object) = &parsed else { panic!("not an object: {:?}", parsed) };
+    assert_eq!((object.name.as_str(), object.live, object.position.timestamp), ("LEADER", true, leader.position.timestamp));
+    assert!((object.position.latitude - leader.position.latitude).abs() < 1e-4);
+    assert_eq!((object.position.course, object.position.speed), (Some(88), Some(36.0)));
+
+    // Killing it only changes the flag, and compressed positions work too
+    let killed = AprsObject { live: false, position: Position { compressed: true, ..leader.position.clone() }, ..leader.clone() };
+    let information = aprs::encode_information(&AprsData::Object(killed)).unwrap();
+    assert!(information.starts_with(";LEADER   _092345z/"), "{}", information);
+    assert!(matches!(aprs::parse_information(&information), Some(AprsData::Object(AprsObject { live: false, .. }))));
+
+    // Items have a name of their own length and no timestamp
+    let aid = AprsObject {
+        name: "AID #2".to_string(),
+        live: true,
+        position: Position { latitude: 49.058333, longitude: -72.029167, symbol_code: '+', comment: "First aid".to_string(), ..Position::default() },
+    };
+    let information = aprs::encode_information(&AprsData::Item(aid.clone())).unwrap();
+    assert_eq!(information, ")AID #2!4903.50N/07201.75W+First aid");
+    let Some(AprsData::Item(item)) = aprs::parse_information(&information) else { panic!("not an item") };
+    assert_eq!((item.name.as_str(), item.live, item.position.comment.as_str()), ("AID #2", true, "First aid"));
+    assert!(matches!(aprs::parse_information(")AID #2_4903.50N/07201.75W+"), Some(AprsData::Item(AprsObject { live: false, .. }))));
+
+    // In JSON the position fields sit next to the name
+    let json = serde_json::to_value(AprsData::Item(aid.clone())).unwrap();
+    assert_eq!((json["type"].as_str(), json["name"].as_str(), json["symbol_code"].as_str()), (Some("item"), Some("AID #2"), Some("+")));
+    let request: AprsData = serde_json::from_str(r#"{"type": "item", "name": "AID #2", "latitude": 49.058333, "longitude": -72.029167, "symbol_code": "+", "comment": "First aid"}"#).unwrap();
+    assert_eq!(request, AprsData::Item(aid.clone()));
+
+    for information in [";LEADER*092345z4903.50N/07201.75W>", ";LEADER   x092345z4903.50N/07201.75W>", ";LEADER   *4903.50N/07201.75W>",
+        ";         *092345z4903.50N/07201.75W>", ")AB!4903.50N/07201.75W+", ")TOOLONGNAME!4903.50N/07201.75W+", ")AID #2!4903.50X/07201.75W+"] {
+        assert_eq!(aprs::parse_information(information), None, "{:?}", information);
+    }
+
+    let untimed = AprsObject { position: Position { timestamp: None, ..leader.position.clone() }, ..leader.clone() };
+    let timed_item = AprsObject { name: "AID".to_string(), ..leader.clone() };
+    for data in [
+        AprsData::Object(untimed),
+        AprsData::Object(AprsObject { name: "CHECKPOINT".to_string(), ..leader.clone() }),
+        AprsData::Item(timed_item),
+        AprsData::Item(AprsObject { name: "AI".to_string(), ..aid.clone() }),
+        AprsData::Item(AprsObject { name: "AID_2".to_string(), ..aid }),
+    ] {
+        assert!(matches!(aprs::encode_information(&data), Err(CodecError::InvalidAprsData(_))), "{:?}", data);
+    }
+}
+
+#[test]
+fn utc_timestamps_are_built_from_the_clock() {
+    let at = |seconds: u64| Timestamp::utc(UNIX_EPOCH + Duration::from_secs(seconds));
+    assert_eq!(at(0), Timestamp::DayHourMinuteUtc { day: 1, hour: 0, minute: 0 });
+    assert_eq!(at(1709210096), Timestamp::DayHourMinuteUtc { day: 29, hour: 12, minute: 34 });
+    assert_eq!(at(1704067140), Timestamp::DayHourMinuteUtc { day: 31, hour: 23, minute: 59 });
+}
+
+#[test]
+fn object_store_beacons_objects_until_they_are_killed() {
+    let mut store = ObjectStore::new(BeaconPolicy { interval: Duration::from_secs(600), kill_beacons: 2 });
+    let start = Instant::now();
+    let at = |seconds: u64| start + Duration::from_secs(seconds);
+    let request = |name: &str, comment: &str| ObjectRequest {
+        source: "N0CALL-9".to_string(),
+        destination: "APRS".to_string(),
+        digipeaters: vec!["WIDE2-1".to_string()],
+        item: false,
+        object: AprsObject {
+            name: name.to_string(),
+            live: true,
+            position: Position {
+                latitude: 49.058333,
+                longitude: -72.029167,
+                symbol_code: 'C',
+                timestamp: Some(Timestamp::DayHourMinuteUtc { day: 18, hour: 9, minute: 0 }),
+                comment: comment.to_string(),
+                ..Position::default()
+            },
+        },
+    };
+
+    store.set(request("CHK1", "Water"), at(0)).unwrap();
+    let mut item = request("CHK2", "");
+    item.item = true;
+    item.object.position.timestamp = None;
+    store.set(item, at(0)).unwrap();
+    store.set(request("CHK3", "Pipes | are fine here"), at(0)).unwrap();
+    assert!(matches!(store.set(ObjectRequest { source: "NOT A CALL".to_string(), ..request("CHK4", "") }, at(0)), Err(CodecError::InvalidCallsign(_))));
+    assert!(matches!(store.set(request("CHECKPOINT", ""), at(0)), Err(CodecError::InvalidAprsData(_))));
+
+    // Everything goes out right away, then every interval, over the air like any beacon
+    let packets = store.due(at(0));
+    assert_eq!(packets.len(), 3);
+    let samples = encoder::encode_frames(&packets.into_iter().map(|p| ScheduledFrame::new(p, 1)).collect::<Vec<_>>(), &EncodeOptions::default()).unwrap();
+    let decoded = decoder::decode_audio(&samples, SAMPLE_RATE, &DecodeOptions::default()).unwrap();
+    assert_eq!(decoded[0].packet.to_tnc2(), "N0CALL-9>APRS,WIDE2-1:;CHK1     *180900z4903.50N/07201.75WCWater");
+    assert_eq!(decoded[1].packet.to_tnc2(), "N0CALL-9>APRS,WIDE2-1:)CHK2!4903.50N/07201.75WC");
+    assert!(matches!(&decoded[0].packet.aprs, Some(AprsData::Object(object)) if object.name == "CHK1"));
+
+    assert!(store.due(at(599)).is_empty());
+    assert_eq!(store.next_due(), Some(at(600)));
+    assert_eq!(store.due(at(600)).len(), 3);
+
+    // Updating an object replaces it and beacons it right away
+    store.set(request("CHK1", "Water and food"), at(700)).unwrap();
+    let packets = store.due(at(700));
+    assert_eq!(packets.len(), 1);
+    assert!(matches!(&packets[0].aprs, Some(AprsData::Object(object)) if object.position.comment == "Water and food"));
+    assert_eq!(store.objects().len(), 3);
+    assert_eq!(store.objects()[0].beacons, 1);
+
+    // A killed object is beaconed as killed until the store is done with it
+    assert!(store.kill("CHK9", at(800)).is_none());
+    assert!(!store.kill("CHK2", at(800)).unwrap().object.live);
+    let packets = store.due(at(800));
+    assert!(matches!(&packets[..], [packet] if packet.aprs.as_ref().is_some_and(|a| matches!(a, AprsData::Item(AprsObject { live: false, .. })))));
+    assert_eq!(store.objects().len(), 3);
+    assert_eq!(store.due(at(1400)).len(), 3);
+    let names: Vec<&str> = store.objects().iter().map(|o| o.object.name.as_str()).collect();
+    assert_eq!(names, vec!["CHK1", "CHK3"]);
+
+    // No more go out at once than a recording holds, the rest wait for the next call
+    let mut store = ObjectStore::default();
+    for i in 0..70 {
+        store.set(request(&format!("CHK{}", i), ""), start).unwrap();
+    }
+    assert_eq!(store.due(start).len(), MAX_TRANSMISSIONS);
+    assert_eq!(store.due(start).len(), 70 - MAX_TRANSMISSIONS);
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn arbitrary_frames_never_panic_the_parser(frame in vec(any::<u8>(), 0..400)) {
+        // Keeping bad FCS frames sends everything through the parser, not just the 1 in 65536 that validate
+        let options = DecodeOptions { keep_bad_fcs: true, ..DecodeOptions::default() };
+        let _ = decoder::decode_frame(frame, &options);
+    }
+
+    #[test]
+    fn truncated_frames_are_errors_not_panics(length in 0usize..25) {
+        // Three addresses, control and protocol ID, and the FCS take 25 bytes, anything shorter is cut
+        let frame = AprsPacket::new("N0CALL-9", "APRS", &["WIDE1-1".to_string()], "Cut short").encode().unwrap();
+        let options = DecodeOptions { keep_bad_fcs: true, ..DecodeOptions::default() };
+        prop_assert!(decoder::decode_frame(frame[..length].to_vec(), &options).is_err());
+    }
+
+    #[test]
+    fn valid_packets_survive_the_frame_round_trip(
+        source in "[A-Z0-9]{1,6}(-(1[0-5]|[1-9]))?",
+        digipeaters in vec("[A-Z0-9]{1,6}", 0..8),
+        information in "[ -~]{0,256}",
+    ) {
+        let frame = AprsPacket::new(&source, "APRS", &digipeaters, &information).encode().unwrap();
+        let decoded = decoder::decode_frame(frame, &DecodeOptions::default()).unwrap();
+        prop_assert_eq!(decoded.packet.source, source);
+        prop_assert_eq!(decoded.packet.digipeaters, digipeaters);
+        prop_assert_eq!(decoded.packet.information, information);
+    }
+
+    #[test]
+    fn arbitrary_information_fields_never_panic_the_aprs_parser(information in "[!=/@][ -~]{0,60}|\\PC{0,60}") {
+        let _ = aprs::parse_information(&information);
+    }
+
+    #[test]
+    fn arbitrary_mic_e_fields_never_panic_the_aprs_parser(
+        destination in "[0-9A-LP-Z]{6}(-[0-9]{1,2})?|[ -~]{0,9}",
+        information in "[`'][\x1c-\x7f]{0,30}|[`'].{0,30}",
+    ) {
+        let _ = aprs::parse_packet(&destination, &information);
+    }
+
+    #[test]
+    fn positions_survive_encoding_in_either_layout(
+        latitude in -90.0f64..=90.0,
+        longitude in -180.0f64..=180.0,
+        compressed in any::<bool>(),
+        comment in "[ -~]{0,40}",
+    ) {
+        let position = Position { latitude, longitude, compressed, comment, ..Position::default() };
+        let information = aprs::encode_information(&AprsData::Position(position.clone())).unwrap();
+        let Some(AprsData::Position(report)) = aprs::parse_information(&information) else {
+            return Err(TestCaseError::fail(format!("{:?} didn't parse", information)));
+        };
+        // Hundredths of a minute uncompressed, a base-91 unit compressed
+        prop_assert!((report.latitude - latitude).abs() < 1e-4 && (report.longitude - longitude).abs() < 1e-4);
+        prop_assert_eq!(report.compressed, compressed);
+        prop_assert_eq!(report.comment, position.comment);
+    }
+
+    #[test]
+    fn arbitrary_bytes_never_panic_the_wav_reader(bytes in vec(any::<u8>(), 0..2000)) {
+        let _ = decoder::read_wav(bytes, None);
+    }
+
+    #[test]
+    fn arbitrary_wav_uploads_never_panic_the_decoder(
+        format in prop_oneof![Just(1u16), Just(3u16), any::<u16>()],
+        channels in 0u16..4,
+        sample_rate in prop_oneof![Just(SAMPLE_RATE), 0u32..200_000, any::<u32>()],
+        bits_per_sample in prop_oneof![Just(16u16), Just(8u16), Just(24u16), any::<u16>()],
+        data in vec(any::<u8>(), 0..4000),
+        parallel in any::<bool>(),
+        channel in option::of(0u16..4),
+    ) {
+        let wav = wav_with_header(format, channels, sample_rate, bits_per_sample, &data);
+        let options = DecodeOptions { parallel, channel, ..DecodeOptions::default() };
+        let _ = decoder::decode_wav(wav, &options);
+    }
+}